@@ -0,0 +1,101 @@
+//! Validation of documents without modifying them for the `check` subcommand.
+
+use crate::find_value;
+use crate::output::to_flow_yaml;
+use serde_yaml::Value;
+use std::fmt;
+
+pub enum Check {
+    /// The value at the path is present and not `null`.
+    Exists(String),
+    /// The value at the path is of the type, see [`Check::TYPES`].
+    Type { path: String, type_name: String },
+    /// The value at the path equals the value.
+    Equals { path: String, value: Value },
+}
+
+impl Check {
+    pub const TYPES: [&'static str; 7] = [
+        "null", "bool", "int", "float", "string", "sequence", "mapping",
+    ];
+
+    fn path(&self) -> &str {
+        match self {
+            Check::Exists(path) | Check::Type { path, .. } | Check::Equals { path, .. } => path,
+        }
+    }
+}
+
+/// A failed check.
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Runs all of the checks against the document and returns the failures.
+pub fn validate(yaml: &Value, checks: &[Check]) -> Vec<ValidationError> {
+    let mut errors = vec![];
+    for check in checks {
+        let path = check.path();
+        let fail = |message: String| ValidationError {
+            path: path.to_string(),
+            message,
+        };
+        let value = match find_value(yaml, path) {
+            Ok(Some(value)) => value,
+            Ok(None) => {
+                errors.push(fail("absent".to_string()));
+                continue;
+            }
+            Err(e) => {
+                errors.push(fail(e.to_string()));
+                continue;
+            }
+        };
+        let value = match value {
+            Value::Tagged(tagged) => &tagged.value,
+            value => value,
+        };
+        match check {
+            Check::Exists(_) if value.is_null() => errors.push(fail("null".to_string())),
+            Check::Exists(_) => {}
+            Check::Type { type_name, .. } => {
+                let actual = type_of(value);
+                if actual != type_name {
+                    errors.push(fail(format!("expected {type_name}, found {actual}")));
+                }
+            }
+            Check::Equals {
+                value: expected, ..
+            } => {
+                if value != expected {
+                    errors.push(fail(format!(
+                        "expected `{}`, found `{}`",
+                        to_flow_yaml(expected).trim_end(),
+                        to_flow_yaml(value).trim_end()
+                    )));
+                }
+            }
+        }
+    }
+    errors
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(n) if n.is_f64() => "float",
+        Value::Number(_) => "int",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "sequence",
+        Value::Mapping(_) => "mapping",
+        Value::Tagged(tagged) => type_of(&tagged.value),
+    }
+}
@@ -0,0 +1,422 @@
+//! Best-effort preservation of the input comments for `--preserve-comments`.
+//!
+//! serde_yaml drops the comments, so they are collected from the input lines
+//! and inserted into the rendered result by the paths of the block mapping
+//! keys and the sequence entries: the full-line comments preceding an entry
+//! go before the same entry of the result and the trailing comment of its line
+//! goes after it. The comments after the last entry of a document stay at its
+//! end. The blank lines between the entries are kept the same way as the
+//! comment lines. The comments of the sequence entries follow the entries if
+//! the sequences change, the entries of the input are matched with the ones of
+//! the result by their values. The comments within flow collections and
+//! multi-line scalars and the ones of the removed entries are lost. The rest
+//! of the input formatting, e.g. the quoting, is not preserved.
+
+use crate::diff::common_subsequence;
+use crate::output::scalar_to_string;
+use crate::output::split_key_value;
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::HashMap;
+
+enum LineKind {
+    Comment,
+    Blank,
+    /// The paths of the entries starting on the line, the outer first, e.g. a
+    /// sequence entry and the first key of its mapping.
+    Entries(Vec<Vec<String>>),
+    DocumentStart,
+    /// A line continuing a value, including the blank lines within it, or a
+    /// directive.
+    Other,
+}
+
+struct Entry {
+    column: usize,
+    segment: String,
+    /// The index of a sequence entry.
+    index: Option<usize>,
+}
+
+#[derive(Default)]
+struct Comments {
+    /// The comment and blank lines, as empty strings, preceding the entries
+    /// of the documents.
+    leading: HashMap<(usize, Vec<String>), Vec<String>>,
+    /// The comments at the ends of the entry lines.
+    trailing: HashMap<(usize, Vec<String>), String>,
+    /// The comment lines after the last entries of the documents.
+    footer: HashMap<usize, Vec<String>>,
+}
+
+/// Inserts the comments of the inputs into the rendered YAML. The comments of
+/// the first input win if several inputs have comments at the same path.
+pub fn restore_comments(inputs: &[String], rendered: &str) -> String {
+    let mut comments = Comments::default();
+    let rendered_docs = documents(rendered);
+    for input in inputs {
+        let mut input_comments = Comments::default();
+        collect_comments(input, &mut input_comments);
+        let mut paths = PathMapping {
+            input: documents(input),
+            result: &rendered_docs,
+            alignments: HashMap::new(),
+        };
+        for (key, leading) in input_comments.leading {
+            if let Some(key) = paths.translate(key) {
+                comments.leading.entry(key).or_insert(leading);
+            }
+        }
+        for (key, trailing) in input_comments.trailing {
+            if let Some(key) = paths.translate(key) {
+                comments.trailing.entry(key).or_insert(trailing);
+            }
+        }
+        for (doc, footer) in input_comments.footer {
+            let current = comments.footer.entry(doc).or_default();
+            if current.is_empty() {
+                *current = footer;
+            }
+        }
+    }
+    let mut result = String::with_capacity(rendered.len() * 2);
+    let mut doc = 0;
+    for (line, kind) in scan(rendered) {
+        match kind {
+            LineKind::DocumentStart => {
+                push_footer(&mut result, &mut comments, doc);
+                doc += 1;
+            }
+            LineKind::Entries(paths) => {
+                let indent = line.len() - line.trim_start_matches(' ').len();
+                for path in &paths {
+                    for comment in comments
+                        .leading
+                        .remove(&(doc, path.clone()))
+                        .unwrap_or_default()
+                    {
+                        match comment.is_empty() {
+                            true => result.push('\n'),
+                            false => result.push_str(&format!("{}{comment}\n", " ".repeat(indent))),
+                        }
+                    }
+                }
+                result.push_str(line);
+                if let Some(comment) = paths
+                    .last()
+                    .and_then(|path| comments.trailing.remove(&(doc, path.clone())))
+                {
+                    result.push_str(&comment);
+                }
+                result.push('\n');
+                continue;
+            }
+            LineKind::Comment | LineKind::Blank | LineKind::Other => {}
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    push_footer(&mut result, &mut comments, doc);
+    result
+}
+
+/// Parses the documents of the YAML stream, none if it is not valid YAML.
+fn documents(yaml: &str) -> Vec<Value> {
+    serde_yaml::Deserializer::from_str(yaml)
+        .map(Value::deserialize)
+        .collect::<Result<_, _>>()
+        .unwrap_or_default()
+}
+
+/// Translates the paths of the input comments into the paths of the same
+/// entries of the result.
+struct PathMapping<'a> {
+    input: Vec<Value>,
+    result: &'a [Value],
+    /// The result indexes of the input sequence entries by the document and
+    /// the input path of the sequence.
+    alignments: HashMap<(usize, Vec<String>), Vec<Option<usize>>>,
+}
+
+impl PathMapping<'_> {
+    /// Returns the path of the entry in the result, `None` if the entry of a
+    /// sequence on the path is removed or cannot be matched.
+    fn translate(&mut self, (doc, path): (usize, Vec<String>)) -> Option<(usize, Vec<String>)> {
+        let mut translated = vec![];
+        for (i, segment) in path.iter().enumerate() {
+            let Some(index) = sequence_index(segment) else {
+                translated.push(segment.clone());
+                continue;
+            };
+            let key = (doc, path[..i].to_vec());
+            if !self.alignments.contains_key(&key) {
+                let input = lookup(self.input.get(doc), &path[..i]);
+                let result = lookup(self.result.get(doc), &translated);
+                let alignment = match (input, result) {
+                    (Some(Value::Sequence(input)), Some(Value::Sequence(result))) => {
+                        align(input, result)
+                    }
+                    _ => vec![],
+                };
+                self.alignments.insert(key.clone(), alignment);
+            }
+            let index = self.alignments[&key].get(index).copied().flatten()?;
+            translated.push(format!("[{index}]"));
+        }
+        Some((doc, translated))
+    }
+}
+
+fn sequence_index(segment: &str) -> Option<usize> {
+    segment.strip_prefix('[')?.strip_suffix(']')?.parse().ok()
+}
+
+/// Returns the value at the path of the comment keys.
+fn lookup<'v>(mut value: Option<&'v Value>, path: &[String]) -> Option<&'v Value> {
+    for segment in path {
+        value = match untag(value?) {
+            Value::Sequence(seq) => seq.get(sequence_index(segment)?),
+            Value::Mapping(map) => map
+                .iter()
+                .find(|(k, _)| scalar_to_string(k) == *segment)
+                .map(|(_, v)| v),
+            _ => None,
+        };
+    }
+    value.map(untag)
+}
+
+fn untag(value: &Value) -> &Value {
+    match value {
+        Value::Tagged(tagged) => untag(&tagged.value),
+        value => value,
+    }
+}
+
+/// Matches the entries of the input sequence with the entries of the result
+/// and returns their result indexes. The equal entries are matched in order
+/// as the longest common subsequence, the unmatched entries between them are
+/// matched by position if their numbers are the same, i.e. they are modified
+/// rather than inserted or removed. None of the entries are matched if the
+/// sequences differ too much.
+fn align(input: &[Value], result: &[Value]) -> Vec<Option<usize>> {
+    let (n, m) = (input.len(), result.len());
+    let mut alignment = vec![None; n];
+    let Some(common) = common_subsequence(input, result) else {
+        return alignment;
+    };
+    let (mut start_i, mut start_j) = (0, 0);
+    for (i, j) in common.into_iter().chain([(n, m)]) {
+        if i - start_i == j - start_j {
+            for k in 0..i - start_i {
+                alignment[start_i + k] = Some(start_j + k);
+            }
+        }
+        if i < n {
+            alignment[i] = Some(j);
+        }
+        (start_i, start_j) = (i + 1, j + 1);
+    }
+    alignment
+}
+
+fn push_footer(result: &mut String, comments: &mut Comments, doc: usize) {
+    for comment in comments.footer.remove(&doc).unwrap_or_default() {
+        result.push_str(&comment);
+        result.push('\n');
+    }
+}
+
+fn collect_comments(input: &str, comments: &mut Comments) {
+    let mut doc = 0;
+    let mut pending = vec![];
+    let mut started = false;
+    // Whether the current document has content before the line.
+    let mut has_content = false;
+    for (line, kind) in scan(input) {
+        match kind {
+            LineKind::Comment => pending.push(line.trim().to_string()),
+            // The blank lines at the start of the documents are dropped.
+            LineKind::Blank if has_content || !pending.is_empty() => pending.push(String::new()),
+            LineKind::Blank => {}
+            LineKind::DocumentStart => {
+                // The comments before the first `---` precede the first entry.
+                if started {
+                    let footer = comments.footer.entry(doc).or_default();
+                    if footer.is_empty() {
+                        *footer = footer_lines(std::mem::take(&mut pending));
+                    }
+                    pending.clear();
+                    doc += 1;
+                }
+                started = true;
+                has_content = false;
+            }
+            LineKind::Entries(paths) => {
+                started = true;
+                has_content = true;
+                if !pending.is_empty() {
+                    let path = paths[0].clone();
+                    let leading = std::mem::take(&mut pending);
+                    comments.leading.entry((doc, path)).or_insert(leading);
+                }
+                if let (Some(path), (_, Some(comment))) = (paths.last(), split_comment(line)) {
+                    let trailing = comment.to_string();
+                    comments
+                        .trailing
+                        .entry((doc, path.clone()))
+                        .or_insert(trailing);
+                }
+            }
+            LineKind::Other => {}
+        }
+    }
+    let footer = comments.footer.entry(doc).or_default();
+    if footer.is_empty() {
+        *footer = footer_lines(pending);
+    }
+}
+
+/// Drops the blank lines at the end of the document.
+fn footer_lines(mut lines: Vec<String>) -> Vec<String> {
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Classifies the lines of the block style YAML tracking the paths of the
+/// entries by their indentation.
+fn scan(yaml: &str) -> Vec<(&str, LineKind)> {
+    let mut lines = vec![];
+    let mut stack: Vec<Entry> = vec![];
+    // The lines indented deeper continue the value of the previous entry.
+    let mut continuation: Option<usize> = None;
+    // The blank lines after a value that may continue on the following lines.
+    let mut blanks = vec![];
+    for line in yaml.lines() {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let trimmed = line.trim();
+        if let Some(threshold) = continuation {
+            if trimmed.is_empty() {
+                blanks.push(lines.len());
+                lines.push((line, LineKind::Blank));
+                continue;
+            }
+            if indent > threshold {
+                for i in blanks.drain(..) {
+                    lines[i].1 = LineKind::Other;
+                }
+                lines.push((line, LineKind::Other));
+                continue;
+            }
+            continuation = None;
+        }
+        blanks.clear();
+        let kind = if trimmed.is_empty() {
+            LineKind::Blank
+        } else if line.starts_with(['%', '.']) {
+            LineKind::Other
+        } else if trimmed.starts_with('#') {
+            LineKind::Comment
+        } else if line == "---" || line.starts_with("--- ") {
+            stack.clear();
+            LineKind::DocumentStart
+        } else {
+            let (content, _) = split_comment(line);
+            let mut column = indent;
+            let mut rest = content[indent..].trim_end();
+            let mut paths = vec![];
+            while rest == "-" || rest.starts_with("- ") {
+                let mut index = 0;
+                while let Some(entry) = stack.pop() {
+                    if entry.column < column || entry.column == column && entry.index.is_none() {
+                        stack.push(entry);
+                        break;
+                    }
+                    if entry.column == column {
+                        index = entry.index.unwrap() + 1;
+                    }
+                }
+                stack.push(Entry {
+                    column,
+                    segment: format!("[{index}]"),
+                    index: Some(index),
+                });
+                paths.push(stack.iter().map(|e| e.segment.clone()).collect());
+                let after = rest[1..].trim_start_matches(' ');
+                column += rest.len() - after.len();
+                rest = after;
+            }
+            let value = match split_key_value(rest) {
+                Some((key, value)) => {
+                    while stack.last().is_some_and(|e| e.column >= column) {
+                        stack.pop();
+                    }
+                    stack.push(Entry {
+                        column,
+                        segment: unquote(key),
+                        index: None,
+                    });
+                    paths.push(stack.iter().map(|e| e.segment.clone()).collect());
+                    value
+                }
+                None => rest,
+            };
+            // A value, unless it is only a tag or an anchor of the nested
+            // collection, may continue on the deeper indented lines.
+            let is_parent = value.is_empty()
+                || value.starts_with(['!', '&']) && !value.contains(' ')
+                || paths.is_empty();
+            if !is_parent {
+                continuation = stack.last().map(|e| e.column);
+            }
+            match paths.is_empty() {
+                true => LineKind::Other,
+                false => LineKind::Entries(paths),
+            }
+        };
+        lines.push((line, kind));
+    }
+    lines
+}
+
+/// Splits the line into the content and the trailing comment, which starts
+/// with the whitespace before a `#` outside of the quoted scalars.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                } else if c == '\\' && q == '"' {
+                    chars.next();
+                }
+            }
+            None => {
+                let before = line[..i].trim_end();
+                if c == '#' && line[..i].ends_with([' ', '\t']) {
+                    let start = before.len();
+                    return (&line[..start], Some(&line[start..]));
+                } else if (c == '"' || c == '\'')
+                    && (before.is_empty() || before.ends_with([':', '-', '?', '[', '{', ',']))
+                {
+                    quote = Some(c);
+                }
+            }
+        }
+    }
+    (line, None)
+}
+
+fn unquote(key: &str) -> String {
+    if let Some(key) = key.strip_prefix('\'').and_then(|k| k.strip_suffix('\'')) {
+        return key.replace("''", "'");
+    }
+    if let Some(key) = key.strip_prefix('"').and_then(|k| k.strip_suffix('"')) {
+        return key.replace("\\\"", "\"").replace("\\\\", "\\");
+    }
+    key.to_string()
+}
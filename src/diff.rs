@@ -0,0 +1,205 @@
+//! Line-level diff of the input and the result for `--dry-run`.
+
+/// The number of unchanged lines shown around the changes.
+const CONTEXT: usize = 3;
+
+struct Line<'a> {
+    tag: char,
+    text: &'a str,
+    /// The numbers of the preceding lines in the old and the new text.
+    old: usize,
+    new: usize,
+}
+
+/// Returns the unified diff of the texts, or an empty string if they have the
+/// same lines.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old, &new);
+    let changed = |k: usize| lines[k].tag != ' ';
+    let mut diff = String::new();
+    let mut next = 0;
+    while let Some(first) = (next..lines.len()).find(|k| changed(*k)) {
+        let start = first.saturating_sub(CONTEXT).max(next);
+        let mut end = first;
+        loop {
+            while end < lines.len() && changed(end) {
+                end += 1;
+            }
+            let unchanged = (end..lines.len()).take_while(|k| !changed(*k)).count();
+            if end + unchanged == lines.len() || unchanged > 2 * CONTEXT {
+                end += unchanged.min(CONTEXT);
+                break;
+            }
+            end += unchanged;
+        }
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|l| l.tag != '+').count();
+        let new_count = hunk.iter().filter(|l| l.tag != '-').count();
+        let old_start = hunk[0].old + usize::from(old_count > 0);
+        let new_start = hunk[0].new + usize::from(new_count > 0);
+        if diff.is_empty() {
+            diff.push_str(&format!("--- {old_name}\n+++ {new_name}\n"));
+        }
+        diff.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+        for line in hunk {
+            diff.push_str(&format!("{}{}\n", line.tag, line.text));
+        }
+        next = end;
+    }
+    diff
+}
+
+/// Matches the lines using their longest common subsequence. Without one,
+/// i.e. with too many changes, all of the lines are replaced.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let common = common_subsequence(old, new).unwrap_or_default();
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (next_i, next_j) in common.into_iter().chain([(old.len(), new.len())]) {
+        let line = |tag, text, i, j| Line {
+            tag,
+            text,
+            old: i,
+            new: j,
+        };
+        while i < next_i {
+            lines.push(line('-', old[i], i, j));
+            i += 1;
+        }
+        while j < next_j {
+            lines.push(line('+', new[j], i, j));
+            j += 1;
+        }
+        if i < old.len() {
+            lines.push(line(' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// The most insertions and removals [`common_subsequence`] looks for, which
+/// bounds its time by `O((N + M) * MAX_EDITS)` and its memory by
+/// `O(MAX_EDITS^2)`.
+const MAX_EDITS: usize = 1000;
+
+/// Returns the index pairs of the longest common subsequence of the items, or
+/// `None` if they differ in more than [`MAX_EDITS`] insertions and removals.
+/// The common prefix and suffix are matched first, so that a few changes of
+/// long inputs are cheap.
+pub(crate) fn common_subsequence<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+) -> Option<Vec<(usize, usize)>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_rest, new_rest) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut common: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let middle = shortest_edit(old_rest, new_rest)?;
+    common.extend(middle.into_iter().map(|(i, j)| (prefix + i, prefix + j)));
+    common.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+    Some(common)
+}
+
+/// Finds the matched pairs of the shortest edit script with Myers' algorithm.
+/// The furthest reaching paths on the diagonals `k = x - y` are kept for
+/// every number of edits `d` to trace the path back.
+fn shortest_edit<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m).min(MAX_EDITS as isize);
+    // The furthest `x` on the diagonal `k` is at `v[k + offset]`.
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // The diagonals `-d - 1..=d + 1` before the step `d`.
+    let mut trace: Vec<Vec<isize>> = vec![];
+    let at = |k: isize| (k + offset) as usize;
+    for d in 0..=max {
+        trace.push(v[at(-d - 1)..=at(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            // Either an insertion from the diagonal above or a removal from
+            // the one below, whichever reaches further.
+            let mut x = match k == -d || k != d && v[at(k - 1)] < v[at(k + 1)] {
+                true => v[at(k + 1)],
+                false => v[at(k - 1)] + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                return Some(trace_back(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn trace_back(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<(usize, usize)> {
+    let mut pairs = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, band) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let v = |k: isize| band[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = match k == -d || k != d && v(k - 1) < v(k + 1) {
+            true => k + 1,
+            false => k - 1,
+        };
+        let previous_x = v(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    pairs.reverse();
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_subsequence_of_long_inputs() {
+        let old: Vec<usize> = (0..20_000).collect();
+        let mut new = old.clone();
+        new[10_000] = 0;
+        new.remove(5_000);
+        new.push(1);
+        let common = common_subsequence(&old, &new).unwrap();
+        assert_eq!(common.len(), 19_998);
+        assert!(common.iter().all(|(i, j)| old[*i] == new[*j]));
+        let reversed: Vec<usize> = old.iter().rev().copied().collect();
+        assert!(common_subsequence(&old, &reversed).is_none());
+    }
+
+    #[test]
+    fn common_subsequence_is_longest() {
+        let (old, new) = (b"ABCABBA", b"CBABAC");
+        let common = common_subsequence(old, new).unwrap();
+        assert_eq!(common.len(), 4);
+        assert!(common
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+        assert!(common.iter().all(|(i, j)| old[*i] == new[*j]));
+    }
+}
@@ -0,0 +1,145 @@
+//! A parser for dotenv files.
+//!
+//! Supported syntax:
+//!
+//! * `KEY=VALUE` lines, optionally prefixed with `export`;
+//! * blank lines and `#` comments, including trailing ` # comments` after
+//!   unquoted values;
+//! * single-quoted literal values and double-quoted values with the `\n`,
+//!   `\t`, `\r`, `\"`, `\\` and `\$` escapes, both may span multiple lines;
+//! * unquoted values continued on the next line with a trailing backslash.
+
+use crate::Result;
+use std::path::Path;
+
+pub fn parse_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| error!(Io: "Failed to read the env file `{path:?}`\nerror=`{e}`"))?;
+    parse(&content).map_err(|e| error!(Parse: "Failed to parse the env file `{path:?}`: {e}"))
+}
+
+pub fn parse(content: &str) -> std::result::Result<Vec<(String, String)>, String> {
+    let mut entries = vec![];
+    let mut chars = content.chars().peekable();
+    let mut line_no = 1;
+    loop {
+        while let Some(c) = chars.next_if(|c| c.is_whitespace()) {
+            if c == '\n' {
+                line_no += 1;
+            }
+        }
+        let Some(&c) = chars.peek() else {
+            break;
+        };
+        if c == '#' {
+            while chars.next_if(|c| *c != '\n').is_some() {}
+            continue;
+        }
+        let mut key: String =
+            std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != '\n')).collect();
+        if chars.next() != Some('=') {
+            return Err(format!(
+                "line {line_no}: expected `KEY=VALUE`, found `{}`",
+                key.trim()
+            ));
+        }
+        key = key.trim().to_string();
+        if let Some(k) = key.strip_prefix("export") {
+            if k.starts_with([' ', '\t']) {
+                key = k.trim_start().to_string();
+            }
+        }
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(format!("line {line_no}: invalid variable name `{key}`"));
+        }
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let mut value = String::new();
+        match chars.peek() {
+            Some('\'') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        None => {
+                            return Err(format!(
+                                "line {line_no}: unterminated quoted value of `{key}`"
+                            ))
+                        }
+                        Some('\'') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line_no += 1;
+                            }
+                            value.push(c);
+                        }
+                    }
+                }
+                skip_trailing(&mut chars);
+            }
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        None => {
+                            return Err(format!(
+                                "line {line_no}: unterminated quoted value of `{key}`"
+                            ))
+                        }
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some('r') => value.push('\r'),
+                            Some(c @ ('"' | '\\' | '$')) => value.push(c),
+                            Some(c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                            None => {
+                                return Err(format!(
+                                    "line {line_no}: unterminated quoted value of `{key}`"
+                                ))
+                            }
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line_no += 1;
+                            }
+                            value.push(c);
+                        }
+                    }
+                }
+                skip_trailing(&mut chars);
+            }
+            _ => loop {
+                let line: String = std::iter::from_fn(|| chars.next_if(|c| *c != '\n')).collect();
+                let comment = match line.starts_with('#') {
+                    true => Some(0),
+                    false => line.find(" #").or_else(|| line.find("\t#")),
+                };
+                let line = match comment {
+                    Some(comment) => &line[..comment],
+                    None => line.as_str(),
+                }
+                .trim_end();
+                match line.strip_suffix('\\') {
+                    Some(continued) => {
+                        value.push_str(continued);
+                        chars.next();
+                        line_no += 1;
+                    }
+                    None => {
+                        value.push_str(line);
+                        break;
+                    }
+                }
+            },
+        }
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Skips the rest of the line after a quoted value, e.g. a trailing comment.
+fn skip_trailing(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| *c != '\n').is_some() {}
+}
@@ -0,0 +1,78 @@
+//! The error type of the library API.
+
+use std::fmt;
+
+/// The category of a failure, which determines the exit code of the command
+/// line tool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorKind {
+    #[default]
+    Other,
+    /// Invalid YAML, JSON or TOML input or values.
+    Parse,
+    /// No value at the path.
+    PathNotFound,
+    /// A referred environment variable is not set.
+    EnvVar,
+    /// The value at the path is of a wrong type for the operation.
+    TypeMismatch,
+    /// Failed reading or writing a file.
+    Io,
+    /// Invalid or conflicting command line arguments.
+    InvalidArgs,
+}
+
+impl ErrorKind {
+    /// The exit code of the command line tool, distinct from the 8 of
+    /// `--fail-if-unchanged`. The invalid arguments share the 2 of `clap`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Parse => 3,
+            ErrorKind::PathNotFound => 4,
+            ErrorKind::EnvVar => 5,
+            ErrorKind::TypeMismatch => 6,
+            ErrorKind::Io => 7,
+            ErrorKind::InvalidArgs => 2,
+        }
+    }
+}
+
+/// A failed transformation. The message describes the failure for the user,
+/// e.g. the path that does not exist in the document.
+#[derive(Debug)]
+pub struct XyamlError {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl XyamlError {
+    pub fn new(message: impl Into<String>) -> XyamlError {
+        XyamlError {
+            kind: ErrorKind::Other,
+            message: message.into(),
+        }
+    }
+
+    pub fn with_kind(self, kind: ErrorKind) -> XyamlError {
+        XyamlError { kind, ..self }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for XyamlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for XyamlError {}
+
+pub type Result<T> = std::result::Result<T, XyamlError>;
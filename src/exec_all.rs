@@ -0,0 +1,95 @@
+//! The command lists of `exec-all`.
+//!
+//! The file is a YAML or JSON sequence of the commands run one after another:
+//!
+//! ```yaml
+//! - cmd: kubeval
+//!   args: [-]
+//!   pipe-yaml: true
+//! - cmd: kubectl
+//!   args: [apply, -f, -]
+//!   pipe-yaml: true
+//! ```
+//!
+//! `args`, `pipe-yaml` and `stdin`, a file connected to the stdin of the
+//! process, are optional. The chain stops at the first command that fails.
+
+use crate::ExecCommand;
+use serde_yaml::Value;
+use std::path::Path;
+use std::path::PathBuf;
+use xyaml::output::scalar_to_string;
+
+pub fn load_commands(path: &Path) -> Vec<ExecCommand> {
+    let content = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| fail!(Io: "Failed to read the commands file `{path:?}`\nerror=`{e}`"));
+    let commands: Value = serde_yaml::from_str(&content).unwrap_or_else(
+        |e| fail!(Parse: "Failed to parse the commands file `{path:?}` as YAML: {e}"),
+    );
+    let Value::Sequence(commands) = commands else {
+        fail!("The commands file `{path:?}` is not a sequence");
+    };
+    if commands.is_empty() {
+        fail!("The commands file `{path:?}` has no commands");
+    }
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| parse_command(path, i, command))
+        .collect()
+}
+
+fn parse_command(path: &Path, i: usize, command: &Value) -> ExecCommand {
+    let Value::Mapping(command) = command else {
+        fail!("The command {i} in `{path:?}` is not a mapping");
+    };
+    if let Some(key) = command
+        .keys()
+        .find(|k| !matches!(k.as_str(), Some("cmd" | "args" | "pipe-yaml" | "stdin")))
+    {
+        fail!(
+            "Unknown key `{}` of the command {i} in `{path:?}`",
+            scalar_to_string(key)
+        );
+    }
+    let cmd = match command.get("cmd") {
+        Some(Value::Mapping(_) | Value::Sequence(_)) => {
+            fail!("The `cmd` of the command {i} in `{path:?}` is not a scalar")
+        }
+        Some(cmd) => PathBuf::from(scalar_to_string(cmd)),
+        None => fail!("The command {i} in `{path:?}` has no `cmd`"),
+    };
+    let args = match command.get("args") {
+        None => &vec![],
+        Some(Value::Sequence(seq)) => seq,
+        Some(_) => fail!("The `args` of the command {i} in `{path:?}` is not a sequence"),
+    };
+    let args = args
+        .iter()
+        .map(|arg| {
+            if arg.is_mapping() || arg.is_sequence() {
+                fail!("The argument `{arg:?}` of the command {i} in `{path:?}` is not a scalar, quote it");
+            }
+            scalar_to_string(arg)
+        })
+        .collect();
+    let pipe_yaml = match command.get("pipe-yaml") {
+        None => false,
+        Some(Value::Bool(pipe_yaml)) => *pipe_yaml,
+        Some(_) => fail!("The `pipe-yaml` of the command {i} in `{path:?}` is not a boolean"),
+    };
+    let stdin = match command.get("stdin") {
+        None => None,
+        Some(Value::String(stdin)) => Some(PathBuf::from(stdin)),
+        Some(_) => fail!("The `stdin` of the command {i} in `{path:?}` is not a string"),
+    };
+    if pipe_yaml && stdin.is_some() {
+        fail!("The command {i} in `{path:?}` has both `pipe-yaml` and `stdin`");
+    }
+    ExecCommand {
+        path: cmd,
+        args,
+        pipe_yaml,
+        stdin,
+    }
+}
@@ -0,0 +1,1906 @@
+//! The YAML transformations behind the `xyaml` command line tool.
+//!
+//! [`transform`] runs the whole pipeline configured by [`Config`] on a YAML
+//! string. [`load`], [`apply`] and [`render_documents`] run its stages
+//! separately, and
+//! [`update_value`], [`set_value`], [`get_value`], [`value_at_path`], [`find_value`],
+//! [`remove_value`] and [`substitute_env`] work on parsed documents directly.
+//! [`merge::deep_merge`] merges documents, [`check::validate`] and
+//! [`schema::validate`] validate them, and [`output`] renders them in the
+//! other formats. All of them report the failures as [`XyamlError`]s instead
+//! of exiting.
+//!
+//! Paths are YAML sequences of mapping keys and `[N]` sequence indexes, e.g.
+//! `[servers, [0], host]`, or dot-separated keys and indexes, e.g.
+//! `servers.0.host` or `servers[0].host`, see [`parse_path`].
+
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+macro_rules! error {
+    ( $kind:ident: $($msg:expr),* ) => {
+        $crate::XyamlError::new(format!($($msg),*)).with_kind($crate::ErrorKind::$kind)
+    };
+    ( $($msg:expr),* ) => {
+        $crate::XyamlError::new(format!($($msg),*))
+    }
+}
+
+macro_rules! bail {
+    ( $($msg:tt)* ) => {
+        return Err(error!($($msg)*))
+    }
+}
+
+/// Prints the warning to <stderr> unless the warnings are suppressed, see
+/// [`set_quiet`].
+#[macro_export]
+macro_rules! warn {
+    ( $($msg:expr),* ) => {
+        if !$crate::is_quiet() {
+            eprintln!($($msg),*);
+        }
+    }
+}
+
+pub mod check;
+pub mod checksum;
+pub mod comments;
+pub mod diff;
+pub mod dotenv;
+mod error;
+pub mod merge;
+pub mod output;
+pub mod regex;
+pub mod schema;
+mod template;
+pub mod toml;
+
+pub use error::ErrorKind;
+pub use error::Result;
+pub use error::XyamlError;
+use output::OutputFormat;
+
+/// Whether the warnings are suppressed.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses the warnings printed to <stderr> by the transformations, e.g.
+/// about the skipped values of the output formats.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub enum Operation {
+    Set {
+        path: String,
+        value: String,
+        hint: ValueHint,
+    },
+    SetDefault {
+        path: String,
+        value: String,
+    },
+    SetJoin {
+        path: String,
+        separator: String,
+        values: Vec<String>,
+    },
+    SetFromChecksum {
+        path: String,
+        algorithm: checksum::Algorithm,
+        file: PathBuf,
+    },
+    SetIfRegex {
+        path: String,
+        pattern: regex::Regex,
+        value: String,
+    },
+    ReplaceInKeys {
+        path: String,
+        old: String,
+        new: String,
+    },
+    SetFromEnvFile {
+        file: PathBuf,
+        path: String,
+    },
+    SetIntegerRange {
+        path: String,
+        start: i64,
+        end: i64,
+        step: i64,
+    },
+    UnsetIfDefault {
+        path: String,
+        default: String,
+    },
+    InjectEnv {
+        path: String,
+        names_path: String,
+    },
+    SetFromFileLines {
+        path: String,
+        file: PathBuf,
+        skip_empty: bool,
+    },
+    SetFromQuery {
+        path: String,
+        file: PathBuf,
+        query: String,
+    },
+    SetFromFile {
+        path: String,
+        file: PathBuf,
+        /// Parse the content as YAML instead of setting it as a string.
+        parse: bool,
+    },
+    ForceString(String),
+    Delete(String),
+    Append {
+        path: String,
+        value: String,
+    },
+    Copy {
+        src: String,
+        dst: String,
+    },
+    ReplacePattern {
+        pattern: regex::Regex,
+        replacement: String,
+    },
+    RepeatLast(usize),
+}
+
+impl Operation {
+    /// Returns the path the operation sets a new value at.
+    fn set_path(&self) -> Option<&str> {
+        match self {
+            Operation::Set { path, .. }
+            | Operation::SetDefault { path, .. }
+            | Operation::SetJoin { path, .. }
+            | Operation::SetFromChecksum { path, .. }
+            | Operation::SetIntegerRange { path, .. }
+            | Operation::InjectEnv { path, .. }
+            | Operation::SetFromFileLines { path, .. }
+            | Operation::SetFromQuery { path, .. }
+            | Operation::SetFromFile { path, .. }
+            | Operation::Copy { dst: path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the change the operation makes and the path it
+    /// makes it at for `verbose`.
+    fn change(&self) -> Option<(&'static str, &str)> {
+        if let Some(path) = self.set_path() {
+            return Some(("set", path));
+        }
+        match self {
+            Operation::SetIfRegex { path, .. } | Operation::ForceString(path) => {
+                Some(("set", path))
+            }
+            Operation::ReplaceInKeys { path, .. } => Some(("replace-in-keys", path)),
+            Operation::SetFromEnvFile { path, .. } => Some(("merge", path)),
+            Operation::UnsetIfDefault { path, .. } => Some(("unset", path)),
+            Operation::Delete(path) => Some(("delete", path)),
+            Operation::Append { path, .. } => Some(("append", path)),
+            _ => None,
+        }
+    }
+}
+
+/// How [`set_value`] treats the current value.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum SetMode {
+    /// The current value is replaced.
+    #[default]
+    Always,
+    /// Fails if the current value is not `null`.
+    RequireNull,
+    /// Only `null` values are replaced, the others are kept.
+    Default,
+}
+
+/// The type the new value of [`update_value`] is converted to.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValueHint {
+    /// The value is parsed as YAML.
+    #[default]
+    Yaml,
+    String,
+    Int,
+    Float,
+    Bool,
+    Null,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl InputFormat {
+    pub const NAMES: [&'static str; 3] = ["yaml", "json", "toml"];
+
+    pub fn from_name(name: &str) -> InputFormat {
+        match name {
+            "json" => InputFormat::Json,
+            "toml" => InputFormat::Toml,
+            _ => InputFormat::Yaml,
+        }
+    }
+
+    /// Returns the format of the file extension, if known.
+    pub fn from_extension(path: &Path) -> Option<InputFormat> {
+        match path.extension()?.to_str()? {
+            "json" => Some(InputFormat::Json),
+            "toml" => Some(InputFormat::Toml),
+            "yaml" | "yml" => Some(InputFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// How the placeholders of the unset variables found by `env_subst_all` are
+/// treated.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum UndefinedVars {
+    #[default]
+    Error,
+    /// The placeholders are left as is.
+    Keep,
+    /// The placeholders are replaced with empty values.
+    Empty,
+}
+
+impl UndefinedVars {
+    pub const NAMES: [&'static str; 3] = ["error", "keep", "empty"];
+
+    pub fn from_name(name: &str) -> UndefinedVars {
+        match name {
+            "keep" => UndefinedVars::Keep,
+            "empty" => UndefinedVars::Empty,
+            _ => UndefinedVars::Error,
+        }
+    }
+}
+
+pub struct Config {
+    pub require_null: bool,
+    pub create: bool,
+    /// Deleting a missing path is a no-op instead of an error.
+    pub delete_missing_ok: bool,
+    pub operations: Vec<Operation>,
+    pub repeat_count: usize,
+    pub env_substitutions: Vec<(String, Option<String>)>,
+    pub env_subst_prefixes: Vec<String>,
+    pub env_subst_prefix_strip: bool,
+    /// Parse the strings with the embedded placeholders as YAML after the
+    /// substitution.
+    pub typed_env_subst: bool,
+    /// The delimiters of the env substitution placeholders, `{{` and `}}` by
+    /// default.
+    pub placeholder_start: String,
+    pub placeholder_end: String,
+    /// Substitute the placeholders of all variables found in the document,
+    /// only of the ones starting with the `env_subst_prefixes` if any.
+    pub env_subst_all: bool,
+    pub env_subst_undefined: UndefinedVars,
+    pub validate_env_subst_coverage: bool,
+    pub strict: bool,
+    pub verbose: bool,
+    pub template: Option<PathBuf>,
+    pub get: Option<String>,
+    /// Printed instead of failing if there is no value at the `get` path.
+    pub get_default: Option<String>,
+    pub document: Option<usize>,
+    /// The path and the value selecting the documents to transform, e.g.
+    /// `kind` and `Deployment`.
+    pub document_selector: Option<(String, Value)>,
+    pub require_keys: Vec<String>,
+    pub require_non_null: Vec<String>,
+    pub merge_from_exec: Vec<String>,
+    pub merge_files: Vec<PathBuf>,
+    pub merge_sequences: merge::SequenceMerge,
+    /// The sequence merge strategies of the paths, override `merge_sequences`.
+    pub merge_strategies: Vec<(String, merge::SequenceMerge)>,
+    pub no_yaml_tags: bool,
+    pub copy_subtrees: Vec<(String, PathBuf, String)>,
+    pub sort_keys: bool,
+    pub sort_keys_at: Vec<String>,
+    pub trim_strings: bool,
+    pub trim_strings_at: Vec<String>,
+    pub trim_keys: bool,
+    pub input_format: InputFormat,
+    pub output_format: OutputFormat,
+    pub path_comments: bool,
+    pub flow_style: bool,
+    pub null_style: output::NullStyle,
+    pub ssm: output::SsmOptions,
+    pub configmap: output::ConfigMapOptions,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            require_null: false,
+            create: false,
+            delete_missing_ok: false,
+            operations: vec![],
+            repeat_count: 1,
+            env_substitutions: vec![],
+            env_subst_prefixes: vec![],
+            env_subst_prefix_strip: false,
+            typed_env_subst: false,
+            placeholder_start: "{{".to_string(),
+            placeholder_end: "}}".to_string(),
+            env_subst_all: false,
+            env_subst_undefined: UndefinedVars::default(),
+            validate_env_subst_coverage: false,
+            strict: false,
+            verbose: false,
+            template: None,
+            get: None,
+            get_default: None,
+            document: None,
+            document_selector: None,
+            require_keys: vec![],
+            require_non_null: vec![],
+            merge_from_exec: vec![],
+            merge_files: vec![],
+            merge_sequences: merge::SequenceMerge::default(),
+            merge_strategies: vec![],
+            no_yaml_tags: false,
+            copy_subtrees: vec![],
+            sort_keys: false,
+            sort_keys_at: vec![],
+            trim_strings: false,
+            trim_strings_at: vec![],
+            trim_keys: false,
+            input_format: InputFormat::default(),
+            output_format: OutputFormat::default(),
+            path_comments: false,
+            flow_style: false,
+            null_style: output::NullStyle::default(),
+            ssm: output::SsmOptions::default(),
+            configmap: output::ConfigMapOptions::default(),
+        }
+    }
+}
+
+/// Parses the YAML, applies the transformations to the targeted documents and
+/// renders the output.
+pub fn transform(yaml: &str, config: &Config) -> Result<String> {
+    let mut documents = load(yaml, config)?;
+    for i in targeted_documents(&documents, config)? {
+        apply(&mut documents[i], config)?;
+    }
+    render_documents(&documents, config)
+}
+
+/// Parses all `---`-separated documents of the input YAML, or the single
+/// JSON or TOML document. An empty input is a single `null` document.
+pub fn load(yaml: &str, config: &Config) -> Result<Vec<Value>> {
+    if config.input_format == InputFormat::Toml {
+        let toml = toml::parse(yaml).map_err(|e| error!(Parse: "Failed to parse TOML: {e}"))?;
+        return Ok(vec![toml]);
+    }
+    if config.input_format == InputFormat::Json {
+        // JSON is a subset of YAML, but the errors should refer to JSON.
+        let json =
+            serde_yaml::from_str(yaml).map_err(|e| error!(Parse: "Failed to parse JSON: {e}"))?;
+        return Ok(vec![json]);
+    }
+    let yaml_string = if config.no_yaml_tags {
+        strip_yaml_tags(yaml)
+    } else {
+        yaml.to_string()
+    };
+
+    let mut documents = vec![];
+    for document in serde_yaml::Deserializer::from_str(&yaml_string) {
+        let yaml =
+            Value::deserialize(document).map_err(|e| error!(Parse: "Failed to parse YAML: {e}"))?;
+        documents.push(yaml);
+    }
+    if documents.is_empty() {
+        documents.push(Value::Null);
+    }
+    Ok(documents)
+}
+
+/// Returns the indexes of the documents the transformations apply to: the
+/// `document` one, the ones with the `document_selector` value, or all of
+/// them.
+pub fn targeted_documents(documents: &[Value], config: &Config) -> Result<Vec<usize>> {
+    if let Some((path, value)) = &config.document_selector {
+        let mut selected = vec![];
+        for (i, document) in documents.iter().enumerate() {
+            if find_value(document, path)? == Some(value) {
+                selected.push(i);
+            }
+        }
+        if selected.is_empty() {
+            bail!(
+                "No document has the value `{}` at the path `{path}`",
+                output::to_flow_yaml(value).trim_end()
+            );
+        }
+        return Ok(selected);
+    }
+    match config.document {
+        None => Ok((0..documents.len()).collect()),
+        Some(n) if n < documents.len() => Ok(vec![n]),
+        Some(n) => bail!(
+            "No document {n} in the input of {} documents",
+            documents.len()
+        ),
+    }
+}
+
+/// Applies the merges, the operations, the env substitutions and the
+/// post-processing to the document.
+pub fn apply(yaml: &mut Value, config: &Config) -> Result<()> {
+    require_all_non_null(yaml, &config.require_keys)?;
+
+    for cmd in config.merge_from_exec.iter() {
+        let overlay = yaml_from_exec(cmd)?;
+        // An empty output is an empty overlay rather than a `null` document.
+        if !overlay.is_null() {
+            merge::deep_merge_with(
+                yaml,
+                &overlay,
+                config.merge_sequences,
+                &config.merge_strategies,
+            )?;
+        }
+    }
+
+    apply_operations(yaml, config)?;
+
+    for file in config.merge_files.iter() {
+        let overlay = yaml_from_file(file)?;
+        if !overlay.is_null() {
+            merge::deep_merge_with(
+                yaml,
+                &overlay,
+                config.merge_sequences,
+                &config.merge_strategies,
+            )?;
+        }
+    }
+
+    let mut vars: HashMap<String, Option<String>> =
+        config.env_substitutions.iter().cloned().collect();
+    let (start, end) = (&config.placeholder_start, &config.placeholder_end);
+    let mut placeholders = placeholders_of(&vars, start, end);
+    for prefix in config.env_subst_prefixes.iter() {
+        for (name, _) in std::env::vars_os() {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let Some(stripped) = name.strip_prefix(prefix.as_str()) else {
+                continue;
+            };
+            let placeholder_name = if config.env_subst_prefix_strip {
+                stripped
+            } else {
+                name
+            };
+            if placeholder_name.is_empty() {
+                continue;
+            }
+            // The explicit `env_substitutions` take precedence.
+            placeholders
+                .entry(format!("{start}{placeholder_name}{end}"))
+                .or_insert_with(|| name.to_string());
+            vars.entry(name.to_string()).or_insert(None);
+        }
+    }
+    if config.env_subst_all {
+        let mut names = vec![];
+        placeholder_names(yaml, start, end, &mut names);
+        for (name, plain) in names {
+            let placeholder = format!("{start}{name}{end}");
+            let is_eligible = config.env_subst_prefixes.is_empty()
+                || config
+                    .env_subst_prefixes
+                    .iter()
+                    .any(|p| name.starts_with(p));
+            if placeholders.contains_key(&placeholder) || !is_eligible {
+                continue;
+            }
+            let default = match std::env::var_os(&name) {
+                Some(_) => None,
+                // The modifiers handle the unset variable themselves.
+                None if !plain => None,
+                None => match config.env_subst_undefined {
+                    UndefinedVars::Error => {
+                        bail!(EnvVar: "The env variable `{name}` of the placeholder `{placeholder}` is not set")
+                    }
+                    UndefinedVars::Keep => continue,
+                    UndefinedVars::Empty => Some(String::new()),
+                },
+            };
+            vars.insert(name.clone(), default);
+            placeholders.insert(placeholder, name);
+        }
+    }
+    let substituted = substitute_placeholders(
+        yaml,
+        &placeholders,
+        &vars,
+        (start, end),
+        config.typed_env_subst,
+        config.verbose,
+    )?;
+    if config.validate_env_subst_coverage {
+        let names: Vec<String> = config
+            .env_substitutions
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect();
+        check_env_subst_coverage(&names, &substituted, config.strict)?;
+    }
+
+    if config.trim_strings || config.trim_keys {
+        trim_strings(yaml, config.trim_strings, config.trim_keys)?;
+    }
+    for path in config.trim_strings_at.iter() {
+        trim_strings(value_at_path(yaml, path)?, true, false)?;
+    }
+
+    if config.sort_keys {
+        sort_keys(yaml, None);
+    }
+    for path in config.sort_keys_at.iter() {
+        let obj = value_at_path(yaml, path)?;
+        if !obj.is_mapping() {
+            bail!(TypeMismatch: "Object at path is not a mapping:\n  obj={obj:?}\n  path=`{path}`");
+        }
+        sort_keys(obj, Some(1));
+    }
+
+    require_all_non_null(yaml, &config.require_non_null)?;
+
+    for (src_path, dst_file, dst_path) in config.copy_subtrees.iter() {
+        copy_subtree(yaml, src_path, dst_file, dst_path)?;
+    }
+    Ok(())
+}
+
+/// Renders the documents joined with `---` lines. Only the targeted documents
+/// are rendered with `get`.
+pub fn render_documents(documents: &[Value], config: &Config) -> Result<String> {
+    let rendered = match config.get {
+        Some(_) => targeted_documents(documents, config)?
+            .into_iter()
+            .map(|i| render(&documents[i], config))
+            .collect::<Result<Vec<_>>>()?,
+        None => documents
+            .iter()
+            .map(|yaml| render(yaml, config))
+            .collect::<Result<Vec<_>>>()?,
+    };
+    Ok(rendered.join("---\n"))
+}
+
+/// Renders the value at the `get` path, or the document with the template or
+/// in the output format.
+pub fn render(yaml: &Value, config: &Config) -> Result<String> {
+    if let Some(path) = &config.get {
+        let value = match (find_value(yaml, path)?, &config.get_default) {
+            (None, Some(default)) => return Ok(format!("{default}\n")),
+            _ => get_value(yaml, path)?,
+        };
+        return Ok(match value {
+            Value::String(s) => format!("{s}\n"),
+            v => output::to_yaml(v)?,
+        });
+    }
+    if let Some(path) = &config.template {
+        return template::render_file(path, yaml);
+    }
+    Ok(match config.output_format {
+        OutputFormat::Yaml => output::to_yaml_with_nulls(yaml, config.null_style, |yaml| {
+            if config.flow_style {
+                return Ok(output::to_flow_yaml(yaml));
+            }
+            let yaml_string = output::to_yaml(yaml)?;
+            Ok(if config.path_comments {
+                output::add_path_comments(&yaml_string, yaml)
+            } else {
+                yaml_string
+            })
+        })?,
+        OutputFormat::AwsSsm => output::to_aws_ssm(yaml, &config.ssm),
+        OutputFormat::KubernetesConfigMap => {
+            output::to_kubernetes_configmap(yaml, &config.configmap)?
+        }
+        OutputFormat::Json => output::to_json(yaml)?,
+        OutputFormat::Shell => output::to_shell(yaml),
+        OutputFormat::Toml => output::to_toml(yaml)?,
+    })
+}
+
+/// Removes the `!!TAG` annotations outside of quoted scalars, block scalars
+/// and comments.
+pub fn strip_yaml_tags(yaml: &str) -> String {
+    scan_yaml_nodes(yaml, |node| node.starts_with("!!"))
+}
+
+pub fn has_yaml_anchors(yaml: &str) -> bool {
+    let mut found = false;
+    scan_yaml_nodes(yaml, |node| {
+        found |= node.starts_with('&') && node[1..].starts_with(|c: char| !c.is_whitespace());
+        false
+    });
+    found
+}
+
+/// Calls `strip` with the rest of the line at the start of every node outside
+/// of quotes, comments and block scalars. The token is removed together with
+/// the following whitespace if `strip` returns `true`.
+fn scan_yaml_nodes(yaml: &str, mut strip: impl FnMut(&str) -> bool) -> String {
+    let mut result = String::with_capacity(yaml.len());
+    let mut block_indent: Option<usize> = None;
+    for line in yaml.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if let Some(block) = block_indent {
+            if line.trim().is_empty() || indent > block {
+                result.push_str(line);
+                continue;
+            }
+            block_indent = None;
+        }
+        let mut stripped = String::with_capacity(line.len());
+        let mut quote = None;
+        let mut chars = line.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match quote {
+                Some(q) => {
+                    if c == q {
+                        quote = None;
+                    } else if c == '\\' && q == '"' {
+                        stripped.push(c);
+                        if let Some((_, escaped)) = chars.next() {
+                            stripped.push(escaped);
+                        }
+                        continue;
+                    }
+                }
+                None => {
+                    let before = line[..i].trim_end();
+                    let at_token_start =
+                        before.is_empty() || before.ends_with([':', '-', '?', '[', '{', ',']);
+                    if c == '#' && (i == 0 || line[..i].ends_with([' ', '\t'])) {
+                        stripped.push_str(&line[i..]);
+                        break;
+                    } else if (c == '"' || c == '\'') && at_token_start {
+                        quote = Some(c);
+                    } else if at_token_start && strip(&line[i..]) {
+                        while chars.next_if(|(_, c)| !c.is_whitespace()).is_some() {}
+                        while chars.next_if(|(_, c)| *c == ' ' || *c == '\t').is_some() {}
+                        continue;
+                    }
+                }
+            }
+            stripped.push(c);
+        }
+        let content = stripped.split(" #").next().unwrap_or_default().trim_end();
+        if content.ends_with(['|', '>'])
+            || content.ends_with(['-', '+']) && content[..content.len() - 1].ends_with(['|', '>'])
+        {
+            block_indent = Some(indent);
+        }
+        result.push_str(&stripped);
+    }
+    result
+}
+
+fn copy_subtree(yaml: &mut Value, src_path: &str, dst_file: &Path, dst_path: &str) -> Result<()> {
+    let subtree = value_at_path(yaml, src_path)?.clone();
+    let dst_string = std::fs::read_to_string(dst_file).map_err(
+        |e| error!(Io: "Failed to read the destination file `{dst_file:?}`\nerror=`{e}`"),
+    )?;
+    let mut dst: Value = serde_yaml::from_str(&dst_string).map_err(
+        |e| error!(Parse: "Failed to parse the destination file `{dst_file:?}` as YAML: {e}"),
+    )?;
+    set_value(&mut dst, dst_path, subtree, SetMode::Always)?;
+    let dst_string = output::to_yaml(&dst)?;
+    write_atomically(dst_file, dst_string.as_bytes(), None)
+}
+
+/// Writes the content into a temporary file next to the target and renames
+/// it over the target, so that readers never observe a partially written file.
+/// Falls back to copying the temporary file if it cannot be renamed, e.g. if
+/// the target is a mount point. Existing non-regular files, e.g. `/dev/null`
+/// or named pipes, are written directly.
+///
+/// The Unix permissions `mode`, e.g. `0o600`, are set on the temporary file
+/// before it is renamed. They are ignored on other platforms and for the
+/// non-regular files. Without them a replaced file keeps its permissions.
+pub fn write_atomically(path: &Path, content: &[u8], mode: Option<u32>) -> Result<()> {
+    if std::fs::metadata(path).is_ok_and(|m| !m.is_file()) {
+        return std::fs::write(path, content)
+            .map_err(|e| error!(Io: "Failed to write the file `{path:?}`\nerror=`{e}`"));
+    }
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| error!("Not a file path: `{path:?}`"))?
+        .to_string_lossy();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let tmp_path = path.with_file_name(format!(".{file_name}.{}.{nanos}.tmp", std::process::id()));
+    std::fs::write(&tmp_path, content).map_err(
+        |e| error!(Io: "Failed to write the temporary file `{tmp_path:?}`\nerror=`{e}`"),
+    )?;
+    // A replaced file keeps its permissions unless the mode is given.
+    let existing = std::fs::metadata(path).ok().map(|m| m.permissions());
+    #[cfg(unix)]
+    let permissions = match mode {
+        Some(mode) => {
+            use std::os::unix::fs::PermissionsExt;
+            Some(std::fs::Permissions::from_mode(mode))
+        }
+        None => existing,
+    };
+    #[cfg(not(unix))]
+    let permissions = {
+        let _ = mode;
+        existing
+    };
+    if let Some(permissions) = permissions {
+        if let Err(e) = std::fs::set_permissions(&tmp_path, permissions) {
+            std::fs::remove_file(&tmp_path).ok();
+            bail!(Io: "Failed to set the permissions of the file `{tmp_path:?}`\nerror=`{e}`");
+        }
+    }
+    let result =
+        std::fs::rename(&tmp_path, path).or_else(|_| std::fs::copy(&tmp_path, path).map(|_| ()));
+    std::fs::remove_file(&tmp_path).ok();
+    result.map_err(|e| error!(Io: "Failed to replace the file `{path:?}`\nerror=`{e}`"))
+}
+
+fn trim_strings(obj: &mut Value, values: bool, keys: bool) -> Result<()> {
+    match obj {
+        Value::String(s) if values => *s = s.trim().to_string(),
+        Value::Mapping(map) => {
+            if keys {
+                let entries = std::mem::take(map);
+                for (key, value) in entries {
+                    let key = match key {
+                        Value::String(k) => Value::String(k.trim().to_string()),
+                        k => k,
+                    };
+                    if map.contains_key(&key) {
+                        bail!(
+                            "Trimming the keys results in the duplicate key `{}`",
+                            segment_to_string(&key)
+                        );
+                    }
+                    map.insert(key, value);
+                }
+            }
+            for (_, value) in map.iter_mut() {
+                trim_strings(value, values, keys)?;
+            }
+        }
+        Value::Sequence(seq) => {
+            for value in seq.iter_mut() {
+                trim_strings(value, values, keys)?;
+            }
+        }
+        Value::Tagged(tagged) => trim_strings(&mut tagged.value, values, keys)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Sorts the mapping keys by their text representation down to the depth,
+/// or through the whole tree if the depth is `None`.
+fn sort_keys(obj: &mut Value, depth: Option<usize>) {
+    if depth == Some(0) {
+        return;
+    }
+    let depth = depth.map(|d| d - 1);
+    match obj {
+        Value::Mapping(map) => {
+            let mut entries: Vec<(Value, Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by_cached_key(|(k, _)| output::scalar_to_string(k));
+            for (key, mut value) in entries {
+                sort_keys(&mut value, depth);
+                map.insert(key, value);
+            }
+        }
+        Value::Sequence(seq) => {
+            for value in seq.iter_mut() {
+                sort_keys(value, depth);
+            }
+        }
+        Value::Tagged(tagged) => sort_keys(&mut tagged.value, depth.map(|d| d + 1)),
+        _ => {}
+    }
+}
+
+fn check_env_subst_coverage(
+    vars: &[String],
+    substituted: &HashSet<String>,
+    strict: bool,
+) -> Result<()> {
+    let unused: Vec<&str> = vars
+        .iter()
+        .filter(|v| !substituted.contains(*v))
+        .map(String::as_str)
+        .collect();
+    if unused.is_empty() {
+        return Ok(());
+    }
+    let msg = format!(
+        "No placeholders found for the env substitutions: {}",
+        unused.join(", ")
+    );
+    if strict {
+        bail!("{msg}");
+    }
+    warn!("Warning: {msg}");
+    Ok(())
+}
+
+fn yaml_from_file(file: &Path) -> Result<Value> {
+    let content = std::fs::read_to_string(file)
+        .map_err(|e| error!(Io: "Failed to read the merge file `{file:?}`\nerror=`{e}`"))?;
+    serde_yaml::from_str(&content).map_err(
+        |e| error!(Parse: "Failed to parse the merge file `{file:?}` as YAML\nerror=`{e}`"),
+    )
+}
+
+fn yaml_from_exec(cmd: &str) -> Result<Value> {
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .map_err(|e| error!("Failed to spawn the merge command:\ncmd=`{cmd}`\nerror=`{e}`"))?;
+    if !output.status.success() {
+        bail!(
+            "The merge command failed:\ncmd=`{cmd}`\nstatus=`{}`",
+            output.status
+        );
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    serde_yaml::from_str(&stdout).map_err(|e| {
+        error!(Parse: "Failed to parse the merge command output as YAML:\ncmd=`{cmd}`\nerror=`{e}`")
+    })
+}
+
+fn apply_operations(yaml: &mut Value, config: &Config) -> Result<()> {
+    let history_len = config
+        .operations
+        .iter()
+        .filter_map(|op| match op {
+            Operation::RepeatLast(n) => Some(*n),
+            _ => None,
+        })
+        .max()
+        .unwrap_or_default();
+    let mut history: VecDeque<&Operation> = VecDeque::with_capacity(history_len);
+    for operation in config.operations.iter() {
+        if let Operation::RepeatLast(n) = operation {
+            if *n > history.len() {
+                bail!(
+                    "Cannot repeat the last {n} operations, only {} precede `--repeat-last`",
+                    history.len()
+                );
+            }
+            for _ in 0..config.repeat_count {
+                for op in history.range(history.len() - n..) {
+                    apply_operation(yaml, op, config)?;
+                }
+            }
+            continue;
+        }
+        apply_operation(yaml, operation, config)?;
+        if history_len > 0 {
+            if history.len() == history_len {
+                history.pop_front();
+            }
+            history.push_back(operation);
+        }
+    }
+    Ok(())
+}
+
+fn apply_operation(yaml: &mut Value, operation: &Operation, config: &Config) -> Result<()> {
+    let change = operation.change().filter(|_| config.verbose);
+    let old_value = match change {
+        Some((_, path)) => find_value(yaml, path).ok().flatten().cloned(),
+        None => None,
+    };
+    do_apply_operation(yaml, operation, config)?;
+    if let Some((name, path)) = change {
+        // The following elements of a sequence are shifted to the deleted index.
+        let new_value = match operation {
+            Operation::Delete(_) => None,
+            _ => find_value(yaml, path).ok().flatten(),
+        };
+        log_change(&format!("{name} {path}"), old_value.as_ref(), new_value);
+    }
+    Ok(())
+}
+
+/// Prints the change to <stderr> in the `[xyaml] NAME: OLD -> NEW` format.
+fn log_change(name: &str, old_value: Option<&Value>, new_value: Option<&Value>) {
+    let repr = |value: Option<&Value>| match value {
+        Some(value) => output::to_flow_yaml(value).trim_end().to_string(),
+        None => "(absent)".to_string(),
+    };
+    eprintln!("[xyaml] {name}: {} -> {}", repr(old_value), repr(new_value));
+}
+
+fn do_apply_operation(yaml: &mut Value, operation: &Operation, config: &Config) -> Result<()> {
+    let mode = match config.require_null {
+        true => SetMode::RequireNull,
+        false => SetMode::Always,
+    };
+    if config.create {
+        if let Some(path) = operation.set_path() {
+            create_path(yaml, path)?;
+        }
+    }
+    match operation {
+        Operation::Set { path, value, hint } => update_value(yaml, path, value, *hint, mode)?,
+        Operation::SetDefault { path, value } => {
+            // A missing key of an existing mapping counts as `null`.
+            if let Ok((Value::Mapping(map), PathSegment::Key(key))) = navigate_to_parent(yaml, path)
+            {
+                map.entry(key).or_insert(Value::Null);
+            }
+            update_value(yaml, path, value, ValueHint::Yaml, SetMode::Default)?
+        }
+        Operation::SetJoin {
+            path,
+            separator,
+            values,
+        } => {
+            let joined = values
+                .iter()
+                .map(|v| resolve_join_value(yaml, v))
+                .collect::<Result<Vec<_>>>()?
+                .join(separator);
+            set_value(yaml, path, Value::String(joined), mode)?;
+        }
+        Operation::SetFromChecksum {
+            path,
+            algorithm,
+            file,
+        } => {
+            let content = std::fs::read(file).map_err(
+                |e| error!(Io: "Failed to read the checksum file `{file:?}`\nerror=`{e}`"),
+            )?;
+            let digest = algorithm.hex_digest(&content);
+            set_value(yaml, path, Value::String(digest), mode)?;
+        }
+        Operation::SetIfRegex {
+            path,
+            pattern,
+            value,
+        } => {
+            let current = value_at_path(yaml, path)?;
+            let matches = match current {
+                Value::Mapping(_) | Value::Sequence(_) => false,
+                v => pattern.is_full_match(&output::scalar_to_string(v)),
+            };
+            if matches {
+                update_value(yaml, path, value, ValueHint::Yaml, mode)?;
+            }
+        }
+        Operation::ReplaceInKeys { path, old, new } => replace_in_keys(yaml, path, old, new)?,
+        Operation::SetFromEnvFile { file, path } => {
+            let vars: serde_yaml::Mapping = dotenv::parse_file(file)?
+                .into_iter()
+                .map(|(k, v)| (Value::String(k), Value::String(v)))
+                .collect();
+            merge::deep_merge(
+                value_at_path(yaml, path)?,
+                &Value::Mapping(vars),
+                merge::SequenceMerge::Replace,
+            );
+        }
+        Operation::SetIntegerRange {
+            path,
+            start,
+            end,
+            step,
+        } => {
+            let range: Vec<Value> = std::iter::successors(Some(*start), |n| n.checked_add(*step))
+                .take_while(|n| if *step > 0 { n < end } else { n > end })
+                .map(Value::from)
+                .collect();
+            set_value(yaml, path, Value::Sequence(range), mode)?;
+        }
+        Operation::UnsetIfDefault { path, default } => {
+            let default: Value = serde_yaml::from_str(default).map_err(|e| {
+                error!(Parse: "Default value is not a valid YAML:\n  default=`{default}`\n  path=`{path}`\n  error=`{e}`")
+            })?;
+            if find_value(yaml, path)? == Some(&default) {
+                remove_value(yaml, path)?;
+            }
+        }
+        Operation::InjectEnv { path, names_path } => {
+            let names = match find_value(yaml, names_path)? {
+                Some(Value::Sequence(names)) => names,
+                Some(_) => {
+                    bail!("Not a sequence of environment variable names\n  path=`{names_path}`")
+                }
+                None => bail!("No sequence of environment variable names\n  path=`{names_path}`"),
+            };
+            let mut vars = serde_yaml::Mapping::new();
+            let mut missing = vec![];
+            for name in names {
+                let Value::String(name) = name else {
+                    bail!("Environment variable name is not a string\n  name=`{name:?}`\n  path=`{names_path}`");
+                };
+                match std::env::var(name) {
+                    Ok(value) => {
+                        vars.insert(Value::String(name.clone()), Value::String(value));
+                    }
+                    Err(_) => missing.push(name.as_str()),
+                }
+            }
+            if !missing.is_empty() {
+                bail!(
+                    "Environment variables are not set: {}\n  path=`{names_path}`",
+                    missing.join(", ")
+                );
+            }
+            set_value(yaml, path, Value::Mapping(vars), mode)?;
+        }
+        Operation::SetFromFileLines {
+            path,
+            file,
+            skip_empty,
+        } => {
+            let content = std::fs::read_to_string(file)
+                .map_err(|e| error!(Io: "Failed to read the file `{file:?}`\nerror=`{e}`"))?;
+            let lines: Vec<Value> = content
+                .lines()
+                .filter(|line| !(*skip_empty && line.is_empty()))
+                .map(|line| Value::String(line.to_string()))
+                .collect();
+            set_value(yaml, path, Value::Sequence(lines), mode)?;
+        }
+        Operation::SetFromFile { path, file, parse } => {
+            let content = std::fs::read_to_string(file)
+                .map_err(|e| error!(Io: "Failed to read the file `{file:?}`\nerror=`{e}`"))?;
+            let value = match parse {
+                true => serde_yaml::from_str(&content).map_err(
+                    |e| error!(Parse: "Failed to parse the file `{file:?}` as YAML\nerror=`{e}`"),
+                )?,
+                false => Value::String(content),
+            };
+            set_value(yaml, path, value, mode)?;
+        }
+        Operation::SetFromQuery { path, file, query } => {
+            let src_string = std::fs::read_to_string(file).map_err(
+                |e| error!(Io: "Failed to read the source file `{file:?}`\nerror=`{e}`"),
+            )?;
+            let src: Value = serde_yaml::from_str(&src_string).map_err(
+                |e| error!(Parse: "Failed to parse the source YAML `{file:?}`\nerror=`{e}`"),
+            )?;
+            let value = find_value(&src, query)?.ok_or_else(
+                || error!(PathNotFound: "No value in the source file `{file:?}`\n  path=`{query}`"),
+            )?;
+            set_value(yaml, path, value.clone(), mode)?;
+        }
+        Operation::ForceString(path) => {
+            let value = value_at_path(yaml, path)?;
+            if !value.is_string() {
+                let string = output::to_yaml(value)?;
+                *value = Value::String(string.trim_end().to_string());
+            }
+        }
+        Operation::Delete(path) => {
+            if config.delete_missing_ok && find_value(yaml, path)?.is_none() {
+                return Ok(());
+            }
+            if mode == SetMode::RequireNull {
+                let current_obj = value_at_path(yaml, path)?;
+                if !current_obj.is_null() {
+                    bail!(TypeMismatch: "Object at path is not `null`:\n  obj={current_obj:?}\n  path=`{path}`");
+                }
+            }
+            remove_value(yaml, path)?;
+        }
+        Operation::Append { path, value } => append_value(yaml, path, value)?,
+        Operation::Copy { src, dst } => {
+            let value = get_value(yaml, src)?.clone();
+            set_value(yaml, dst, value, mode)?;
+        }
+        Operation::ReplacePattern {
+            pattern,
+            replacement,
+        } => regex_replace_all(yaml, pattern, replacement),
+        Operation::RepeatLast(_) => unreachable!("repeats are expanded by apply_operations"),
+    }
+    Ok(())
+}
+
+/// Replaces the matches of the pattern in every string value of the tree.
+fn regex_replace_all(obj: &mut Value, pattern: &regex::Regex, replacement: &str) {
+    match obj {
+        Value::String(s) => *s = pattern.replace_all(s, replacement),
+        Value::Sequence(seq) => seq
+            .iter_mut()
+            .for_each(|v| regex_replace_all(v, pattern, replacement)),
+        Value::Mapping(map) => map
+            .values_mut()
+            .for_each(|v| regex_replace_all(v, pattern, replacement)),
+        Value::Tagged(tagged) => regex_replace_all(&mut tagged.value, pattern, replacement),
+        _ => {}
+    }
+}
+
+fn replace_in_keys(yaml: &mut Value, path: &str, old: &str, new: &str) -> Result<()> {
+    let obj = value_at_path(yaml, path)?;
+    let Some(map) = obj.as_mapping_mut() else {
+        bail!(TypeMismatch: "Object at path is not a mapping:\n  obj={obj:?}\n  path=`{path}`");
+    };
+    let entries = std::mem::take(map);
+    for (key, value) in entries {
+        let key = match key {
+            Value::String(k) if !old.is_empty() => Value::String(k.replace(old, new)),
+            k => k,
+        };
+        if map.contains_key(&key) {
+            bail!(
+                "Replacing `{old}` with `{new}` results in the duplicate key `{}`\n  path=`{path}`",
+                segment_to_string(&key)
+            );
+        }
+        map.insert(key, value);
+    }
+    Ok(())
+}
+
+fn resolve_join_value(yaml: &mut Value, value: &str) -> Result<String> {
+    if let Some(literal) = value.strip_prefix("@@") {
+        return Ok(format!("@{literal}"));
+    }
+    let Some(path) = value.strip_prefix('@') else {
+        return Ok(value.to_string());
+    };
+    Ok(match value_at_path(yaml, path)? {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        v => bail!(TypeMismatch: "Referenced value is not a scalar:\n  obj={v:?}\n  path=`{path}`"),
+    })
+}
+
+/// Substitutes the placeholders of the variables and returns the names of the
+/// variables that had at least one placeholder in the document. The default
+/// of a variable is used if it is not set in the environment. The substituted
+/// values are printed to <stderr> with `verbose`.
+pub fn substitute_env(
+    obj: &mut Value,
+    vars: &HashMap<String, Option<String>>,
+    verbose: bool,
+) -> Result<HashSet<String>> {
+    let placeholders = placeholders_of(vars, "{{", "}}");
+    substitute_placeholders(obj, &placeholders, vars, ("{{", "}}"), false, verbose)
+}
+
+/// Collects the names of the variables of the placeholders in the keys and
+/// the string values, e.g. `VAR` of `{{VAR}}` or `{{VAR:-DEFAULT}}`, with
+/// whether the variable has a placeholder without a modifier. The names are
+/// the environment variable names, other placeholders, e.g. of templates, are
+/// skipped.
+fn placeholder_names(v: &Value, start: &str, end: &str, names: &mut Vec<(String, bool)>) {
+    let scan = |s: &str, names: &mut Vec<(String, bool)>| {
+        for (i, _) in s.match_indices(start) {
+            let rest = &s[i + start.len()..];
+            let Some(inner) = rest.find(end).map(|j| &rest[..j]) else {
+                continue;
+            };
+            let (name, modifier) = split_modifier(inner);
+            let is_var_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_var_name {
+                continue;
+            }
+            match names.iter_mut().find(|(n, _)| n == name) {
+                Some((_, plain)) => *plain |= modifier.is_none(),
+                None => names.push((name.to_string(), modifier.is_none())),
+            }
+        }
+    };
+    match v {
+        Value::String(s) => scan(s, names),
+        Value::Mapping(map) => {
+            for (key, value) in map.iter() {
+                if let Value::String(key) = key {
+                    scan(key, names);
+                }
+                placeholder_names(value, start, end, names);
+            }
+        }
+        Value::Sequence(seq) => {
+            for value in seq {
+                placeholder_names(value, start, end, names);
+            }
+        }
+        Value::Tagged(tagged) => placeholder_names(&tagged.value, start, end, names),
+        _ => {}
+    }
+}
+
+/// Returns the placeholders of the variables, e.g. `{{VAR}}`, mapped to their
+/// names.
+fn placeholders_of(
+    vars: &HashMap<String, Option<String>>,
+    start: &str,
+    end: &str,
+) -> HashMap<String, String> {
+    vars.keys()
+        .map(|v| (format!("{start}{v}{end}"), v.clone()))
+        .collect()
+}
+
+/// The placeholders of the env substitution, mapped to the variables, with
+/// their delimiters.
+struct Placeholders<'a> {
+    placeholders: &'a HashMap<String, String>,
+    vars: &'a HashMap<String, Option<String>>,
+    start: &'a str,
+    end: &'a str,
+}
+
+impl Placeholders<'_> {
+    /// Expands the placeholder at the start of the string and returns the
+    /// value with the length of the placeholder, `None` if it is not one of
+    /// the variables. `{{VAR:-DEFAULT}}` falls back to `DEFAULT` and
+    /// `{{VAR:?MESSAGE}}` fails with `MESSAGE` if the variable is unset or
+    /// empty.
+    fn expand(&self, s: &str, used: &mut HashSet<String>) -> Result<Option<(String, usize)>> {
+        let Some(inner) = s[self.start.len()..].split(self.end).next() else {
+            return Ok(None);
+        };
+        if inner.len() == s.len() - self.start.len() {
+            return Ok(None);
+        }
+        let (name, modifier) = split_modifier(inner);
+        let placeholder = format!("{}{name}{}", self.start, self.end);
+        let Some(var) = self.placeholders.get(&placeholder) else {
+            return Ok(None);
+        };
+        used.insert(var.clone());
+        let value = match modifier {
+            None => env_var(var, &self.vars[var])?,
+            Some((op, arg)) => {
+                let value = std::env::var(var).ok().or_else(|| self.vars[var].clone());
+                match (value.filter(|v| !v.is_empty()), op) {
+                    (Some(value), _) => value,
+                    (None, '-') => arg.to_string(),
+                    (None, _) if arg.is_empty() => {
+                        bail!(EnvVar: "The referred env variable `{var}` is not set or empty")
+                    }
+                    (None, _) => bail!(EnvVar: "{arg}\n  env_var=`{var}`"),
+                }
+            }
+        };
+        Ok(Some((
+            value,
+            self.start.len() + inner.len() + self.end.len(),
+        )))
+    }
+
+    /// Replaces the placeholders embedded in the string with the variable
+    /// values.
+    fn interpolate(&self, s: &str, used: &mut HashSet<String>) -> Result<String> {
+        let mut interpolated = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(i) = rest.find(self.start) {
+            interpolated.push_str(&rest[..i]);
+            rest = &rest[i..];
+            match self.expand(rest, used)? {
+                Some((value, len)) => {
+                    interpolated.push_str(&value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    interpolated.push_str(self.start);
+                    rest = &rest[self.start.len()..];
+                }
+            }
+        }
+        interpolated.push_str(rest);
+        Ok(interpolated)
+    }
+}
+
+/// Splits the placeholder content into the name and the `:-` or `:?`
+/// modifier with its argument.
+fn split_modifier(inner: &str) -> (&str, Option<(char, &str)>) {
+    for (i, _) in inner.match_indices(':') {
+        let rest = &inner[i + 1..];
+        if let Some(op @ ('-' | '?')) = rest.chars().next() {
+            return (&inner[..i], Some((op, &rest[1..])));
+        }
+    }
+    (inner, None)
+}
+
+/// Substitutes the placeholders with the values of the variables they are
+/// mapped to, see [`substitute_env`]. With `typed` the strings with the
+/// embedded placeholders are parsed as YAML after the substitution and are
+/// kept as strings if they are not valid YAML.
+fn substitute_placeholders(
+    obj: &mut Value,
+    placeholders: &HashMap<String, String>,
+    vars: &HashMap<String, Option<String>>,
+    (start, end): (&str, &str),
+    typed: bool,
+    verbose: bool,
+) -> Result<HashSet<String>> {
+    let placeholders = Placeholders {
+        placeholders,
+        vars,
+        start,
+        end,
+    };
+    let mut used = HashSet::new();
+    do_substitute_env(obj, &placeholders, typed, &mut used, verbose)?;
+    Ok(used)
+}
+
+fn do_substitute_env(
+    obj: &mut Value,
+    placeholders: &Placeholders,
+    typed: bool,
+    used: &mut HashSet<String>,
+    verbose: bool,
+) -> Result<()> {
+    let start = placeholders.start;
+    if let Some(map) = obj.as_mapping_mut() {
+        if map
+            .keys()
+            .any(|k| k.as_str().is_some_and(|k| k.contains(start)))
+        {
+            // The mapping is rebuilt to keep the order of the entries.
+            for (key, value) in std::mem::take(map) {
+                let key = match key.as_str() {
+                    Some(k) if k.contains(start) => {
+                        let new_key = Value::String(placeholders.interpolate(k, used)?);
+                        if verbose && new_key != key {
+                            log_change("env-subst", Some(&key), Some(&new_key));
+                        }
+                        new_key
+                    }
+                    _ => key,
+                };
+                if map.contains_key(&key) {
+                    bail!("Duplicate key after the env substitution:\n  key={key:?}");
+                }
+                map.insert(key, value);
+            }
+        }
+        for (_, obj) in map.iter_mut() {
+            do_substitute_env(obj, placeholders, typed, used, verbose)?;
+        }
+    } else if let Some(seq) = obj.as_sequence_mut() {
+        for obj in seq.iter_mut() {
+            do_substitute_env(obj, placeholders, typed, used, verbose)?;
+        }
+    } else if let Some(s) = obj.as_str() {
+        let old_value = verbose.then(|| obj.clone());
+        let whole = match s.starts_with(start) {
+            true => placeholders
+                .expand(s, used)?
+                .filter(|(_, len)| *len == s.len()),
+            false => None,
+        };
+        if let Some((new_value, _)) = whole {
+            *obj = serde_yaml::from_str(&new_value).map_err(|e| {
+                error!(Parse: "New value is not a valid YAML:\n  new_value=`{new_value}`\n  placeholder=`{s}`\n  error=`{e}`")
+            })?;
+        } else if s.contains(start) {
+            let interpolated = placeholders.interpolate(s, used)?;
+            *obj = match typed {
+                true => serde_yaml::from_str(&interpolated)
+                    .unwrap_or_else(|_| Value::String(interpolated)),
+                // Embedded placeholders keep the value a string.
+                false => Value::String(interpolated),
+            };
+        }
+        if let Some(old_value) = old_value.filter(|v| v != obj) {
+            log_change("env-subst", Some(&old_value), Some(obj));
+        }
+    }
+    Ok(())
+}
+
+fn env_var(var: &str, default: &Option<String>) -> Result<String> {
+    match (std::env::var(var), default) {
+        (Ok(value), _) => Ok(value),
+        (Err(std::env::VarError::NotPresent), Some(default)) => Ok(default.clone()),
+        (Err(e), _) => {
+            bail!(EnvVar: "Failed to read the referred env variable `{var}`\nerror=`{e}`")
+        }
+    }
+}
+
+/// Flattens the nested mappings into the paths of their non-mapping values
+/// and the values, both serialized as YAML as accepted by [`update_value`].
+pub fn flatten_to_paths(v: &Value, prefix: Vec<Value>) -> Result<Vec<(String, String)>> {
+    match v {
+        Value::Mapping(map) if !map.is_empty() => {
+            let mut paths = vec![];
+            for (key, value) in map.iter() {
+                let mut path = prefix.clone();
+                path.push(key.clone());
+                paths.extend(flatten_to_paths(value, path)?);
+            }
+            Ok(paths)
+        }
+        v => {
+            let path = output::to_flow_yaml(&Value::Sequence(prefix));
+            let value = output::to_yaml(v)?;
+            Ok(vec![(path.trim_end().to_string(), value)])
+        }
+    }
+}
+
+/// Converts the new value according to the hint and sets it at the path.
+pub fn update_value(
+    obj: &mut Value,
+    path: &str,
+    new_value: &str,
+    hint: ValueHint,
+    mode: SetMode,
+) -> Result<()> {
+    let invalid = |expected: &str| error!(Parse: "New value is not {expected}:\n  new_value=`{new_value}`\n  path=`{path}`");
+    let new_value = match hint {
+        ValueHint::Yaml => serde_yaml::from_str(new_value).map_err(|e| {
+            error!(Parse: "New value is no a valid YAML:\n  new_value=`{new_value}`\n  path=`{path}`\n  error=`{e}`")
+        })?,
+        ValueHint::String => Value::String(new_value.to_string()),
+        ValueHint::Int => {
+            let int = new_value.trim();
+            match int.parse::<i64>() {
+                Ok(int) => Value::from(int),
+                Err(_) => Value::from(int.parse::<u64>().map_err(|_| invalid("an integer"))?),
+            }
+        }
+        ValueHint::Float => Value::from(
+            new_value
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| invalid("a number"))?,
+        ),
+        ValueHint::Bool => match new_value.trim() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => return Err(invalid("`true` or `false`")),
+        },
+        ValueHint::Null => match new_value.trim() {
+            "" | "~" | "null" => Value::Null,
+            _ => return Err(invalid("`null`")),
+        },
+    };
+    set_value(obj, path, new_value, mode)
+}
+
+/// Parses the new value as YAML and pushes it onto the sequence at the path.
+pub fn append_value(obj: &mut Value, path: &str, new_value: &str) -> Result<()> {
+    let new_value = serde_yaml::from_str(new_value).map_err(|e| {
+        error!(Parse: "New value is no a valid YAML:\n  new_value=`{new_value}`\n  path=`{path}`\n  error=`{e}`")
+    })?;
+    let current_obj = value_at_path(obj, path)?;
+    let Some(seq) = current_obj.as_sequence_mut() else {
+        bail!(TypeMismatch: "Object at path is not a sequence:\n  obj={current_obj:?}\n  path=`{path}`");
+    };
+    seq.push(new_value);
+    Ok(())
+}
+
+/// Sets the new value at the path, or at all of the paths it matches if it
+/// has `*` segments.
+pub fn set_value(obj: &mut Value, path: &str, new_value: Value, mode: SetMode) -> Result<()> {
+    let targets = values_at_path(obj, path)?;
+    if mode == SetMode::RequireNull {
+        if let Some(current_obj) = targets.iter().find(|v| !v.is_null()) {
+            bail!(TypeMismatch: "Object at path is not `null`:\n  obj={current_obj:?}\n  path=`{path}`");
+        }
+    }
+    for target in targets {
+        if mode != SetMode::Default || target.is_null() {
+            *target = new_value.clone();
+        }
+    }
+    Ok(())
+}
+
+pub enum PathSegment {
+    Key(Value),
+    Index(i64),
+    /// `*`, matches all entries of a mapping or a sequence.
+    Wildcard,
+}
+
+fn wildcard_error(path: &str) -> XyamlError {
+    error!("Wildcards are only supported in the paths of the set values\n  path=`{path}`")
+}
+
+/// Resolves the index in a sequence of the given length, negative indexes
+/// count from the end, e.g. `-1` is the last element.
+fn resolve_index(idx: i64, len: usize) -> Option<usize> {
+    let idx = if idx < 0 {
+        len.checked_sub(idx.unsigned_abs() as usize)?
+    } else {
+        idx as usize
+    };
+    (idx < len).then_some(idx)
+}
+
+/// Parses a YAML sequence path, e.g. `[items, [0], name]`, or a dot-separated
+/// path, e.g. `items.0.name` or `items[0].name`. In the latter all-digit
+/// segments, optionally prefixed with `-`, and the bracketed suffixes of the
+/// keys are indexes, `\\.` and `\\[` are a literal dot and bracket within a key.
+/// Negative indexes count from the end of the sequence. A `*` segment matches
+/// all entries of a mapping or a sequence in the paths of the set values.
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>> {
+    if path.trim_start().starts_with('[') {
+        parse_sequence_path(path)
+    } else {
+        parse_dot_path(path)
+    }
+}
+
+fn parse_dot_path(path: &str) -> Result<Vec<PathSegment>> {
+    // The segments with whether they are bracketed.
+    let mut keys = vec![(String::new(), false)];
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => keys.push((String::new(), false)),
+            '[' if !keys.last().unwrap().0.is_empty() => {
+                let mut index = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => index.push(c),
+                        None => bail!("Unclosed `[` in the path `{path}`"),
+                    }
+                }
+                keys.push((index, true));
+                // Another index may follow, e.g. `matrix[0][1]`.
+                match chars.peek() {
+                    None | Some('.' | '[') => {}
+                    Some(c) => bail!("Unexpected `{c}` after `]` in the path `{path}`"),
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c @ ('.' | '\\' | '[')) => keys.last_mut().unwrap().0.push(c),
+                Some(c) => keys.last_mut().unwrap().0.extend(['\\', c]),
+                None => keys.last_mut().unwrap().0.push('\\'),
+            },
+            c => keys.last_mut().unwrap().0.push(c),
+        }
+    }
+    keys.into_iter()
+        .enumerate()
+        .map(|(i, (key, bracketed))| {
+            if key.is_empty() {
+                bail!("Empty key at the position {i} of the path `{path}`");
+            }
+            let digits = key.strip_prefix('-').unwrap_or(&key);
+            let is_index = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+            Ok(match key.parse::<i64>() {
+                _ if key == "*" => PathSegment::Wildcard,
+                Ok(idx) if is_index => PathSegment::Index(idx),
+                _ if bracketed => bail!("Invalid index `[{key}]` in the path `{path}`"),
+                _ => PathSegment::Key(Value::String(key)),
+            })
+        })
+        .collect()
+}
+
+fn parse_sequence_path(path: &str) -> Result<Vec<PathSegment>> {
+    let segments: Value = serde_yaml::from_str(&quote_wildcards(path))
+        .map_err(|e| error!("Failed to parse the path as YAML:\n`{path}`\nerror: {e}"))?;
+    if !segments.is_sequence() {
+        bail!("Path is not a YAML sequence:\n`{path}`")
+    }
+    let segments = segments.as_sequence().unwrap();
+    let mut cursor = vec![];
+    let mut result = vec![];
+    for segment in segments.iter() {
+        cursor.push(segment_to_string(segment));
+        if segment.is_sequence() {
+            let seq = segment.as_sequence().unwrap();
+            if seq.len() != 1 {
+                bail!("Multiple sequence indexes are not supported\n  cursor=`{cursor:?}`\n  path=`{path}`");
+            }
+            let idx = seq.first().unwrap();
+            let Some(idx) = idx.as_i64() else {
+                bail!("Invalid sequence index `{idx:?}`\n  cursor=`{cursor:?}`\n  path=`{path}`");
+            };
+            result.push(PathSegment::Index(idx));
+        } else if segment.as_str() == Some("*") {
+            result.push(PathSegment::Wildcard);
+        } else {
+            result.push(PathSegment::Key(segment.clone()));
+        }
+    }
+    Ok(result)
+}
+
+/// Quotes the bare `*` segments of a YAML sequence path, which YAML would
+/// parse as aliases otherwise.
+fn quote_wildcards(path: &str) -> String {
+    let chars: Vec<char> = path.chars().collect();
+    let mut result = String::new();
+    let mut quote = None;
+    for (i, &c) in chars.iter().enumerate() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '*' => {
+                let before = chars[..i].iter().rev().find(|c| !c.is_whitespace());
+                let after = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if matches!(before, Some('[' | ',')) && matches!(after, Some(']' | ',')) {
+                    result.push_str("'*'");
+                    continue;
+                }
+            }
+            None => {}
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn segment_to_string(segment: &Value) -> String {
+    output::to_flow_yaml(segment).trim_end().to_string()
+}
+
+/// Returns the value at the path, failing if any of the path segments does
+/// not exist.
+pub fn value_at_path<'a>(obj: &'a mut Value, path: &str) -> Result<&'a mut Value> {
+    value_at_segments(obj, &parse_path(path)?, path)
+}
+
+/// Returns the values at the path, where `*` segments match all entries of a
+/// mapping or a sequence, failing if any of the path segments does not exist.
+pub fn values_at_path<'a>(obj: &'a mut Value, path: &str) -> Result<Vec<&'a mut Value>> {
+    let segments = parse_path(path)?;
+    let mut groups = segments.split(|s| matches!(s, PathSegment::Wildcard));
+    let first = groups.next().unwrap();
+    let mut current = vec![value_at_segments(obj, first, path)?];
+    for group in groups {
+        let mut next = vec![];
+        for obj in current {
+            let entries: Vec<&mut Value> = match obj {
+                Value::Mapping(map) => map.values_mut().collect(),
+                Value::Sequence(seq) => seq.iter_mut().collect(),
+                obj => {
+                    bail!(TypeMismatch: "Wildcard on a non-collection value:\n  obj={obj:?}\n  path=`{path}`")
+                }
+            };
+            for entry in entries {
+                next.push(value_at_segments(entry, group, path)?);
+            }
+        }
+        current = next;
+    }
+    Ok(current)
+}
+
+fn value_at_segments<'a>(
+    obj: &'a mut Value,
+    segments: &[PathSegment],
+    path: &str,
+) -> Result<&'a mut Value> {
+    let mut cursor = vec![];
+    let mut current_obj = obj;
+    for segment in segments {
+        match segment {
+            PathSegment::Index(idx) => {
+                cursor.push(format!("[{idx}]"));
+                let entry = match current_obj {
+                    Value::Sequence(seq) => resolve_index(*idx, seq.len()).map(|i| &mut seq[i]),
+                    obj => obj.get_mut(Value::from(*idx)),
+                };
+                current_obj = entry.ok_or_else(|| {
+                    error!(PathNotFound: "No entry at index {idx}\n  cursor=`{cursor:?}`\n  path=`{path}`")
+                })?;
+            }
+            PathSegment::Key(key) => {
+                let segment_str = segment_to_string(key);
+                cursor.push(segment_str.clone());
+                current_obj = current_obj.get_mut(key).ok_or_else(|| {
+                    error!(PathNotFound: "No key `{segment_str}`\n  cursor=`{cursor:?}`\n  path=`{path}`")
+                })?;
+            }
+            PathSegment::Wildcard => return Err(wildcard_error(path)),
+        }
+    }
+    Ok(current_obj)
+}
+
+/// Creates the missing entries along the path as `null`. `null` values along
+/// the path become mappings or sequences depending on the following segment,
+/// sequences are extended with `null` up to the index. `*` segments match the
+/// existing entries only.
+pub fn create_path(obj: &mut Value, path: &str) -> Result<()> {
+    create_segments(obj, &parse_path(path)?, &mut vec![], path)
+}
+
+fn create_segments(
+    obj: &mut Value,
+    segments: &[PathSegment],
+    cursor: &mut Vec<String>,
+    path: &str,
+) -> Result<()> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Ok(());
+    };
+    if obj.is_null() {
+        *obj = match segment {
+            PathSegment::Index(_) => Value::Sequence(vec![]),
+            PathSegment::Key(_) => Value::Mapping(serde_yaml::Mapping::new()),
+            // There is nothing to match, setting the value reports it.
+            PathSegment::Wildcard => return Ok(()),
+        };
+    }
+    let next = match (segment, obj) {
+        (PathSegment::Wildcard, obj) => {
+            let entries: Vec<&mut Value> = match obj {
+                Value::Mapping(map) => map.values_mut().collect(),
+                Value::Sequence(seq) => seq.iter_mut().collect(),
+                _ => vec![],
+            };
+            cursor.push("*".to_string());
+            for entry in entries {
+                let len = cursor.len();
+                create_segments(entry, rest, cursor, path)?;
+                cursor.truncate(len);
+            }
+            return Ok(());
+        }
+        (PathSegment::Index(idx), Value::Sequence(seq)) => {
+            cursor.push(format!("[{idx}]"));
+            let idx = if *idx < 0 {
+                resolve_index(*idx, seq.len()).ok_or_else(|| {
+                    error!(PathNotFound: "No entry at index {idx}\n  cursor=`{cursor:?}`\n  path=`{path}`")
+                })?
+            } else {
+                *idx as usize
+            };
+            if seq.len() <= idx {
+                seq.resize(idx + 1, Value::Null);
+            }
+            &mut seq[idx]
+        }
+        (PathSegment::Index(idx), Value::Mapping(map)) => {
+            cursor.push(format!("[{idx}]"));
+            map.entry(Value::from(*idx)).or_insert(Value::Null)
+        }
+        (PathSegment::Key(key), Value::Mapping(map)) => {
+            cursor.push(segment_to_string(key));
+            map.entry(key.clone()).or_insert(Value::Null)
+        }
+        (segment, obj) => {
+            if let PathSegment::Key(key) = segment {
+                cursor.push(segment_to_string(key));
+            }
+            bail!(TypeMismatch: "Cannot create the entry in a non-collection value:\n  obj={obj:?}\n  cursor=`{cursor:?}`\n  path=`{path}`");
+        }
+    };
+    create_segments(next, rest, cursor, path)
+}
+
+/// Returns the parent of the value at the path together with the last path
+/// segment, failing if the path is empty or the parent does not exist.
+fn navigate_to_parent<'a>(obj: &'a mut Value, path: &str) -> Result<(&'a mut Value, PathSegment)> {
+    let mut segments = parse_path(path)?;
+    let last = segments
+        .pop()
+        .ok_or_else(|| error!("The path points to the document root\n  path=`{path}`"))?;
+    Ok((value_at_segments(obj, &segments, path)?, last))
+}
+
+/// Removes the entry at the path from its parent mapping or sequence and
+/// returns it.
+pub fn remove_value(obj: &mut Value, path: &str) -> Result<Value> {
+    let (parent, last) = navigate_to_parent(obj, path)?;
+    let removed = match (last, parent) {
+        (PathSegment::Wildcard, _) => return Err(wildcard_error(path)),
+        (PathSegment::Key(key), Value::Mapping(map)) => map.shift_remove(&key),
+        (PathSegment::Index(idx), Value::Mapping(map)) => map.shift_remove(Value::from(idx)),
+        (PathSegment::Index(idx), Value::Sequence(seq)) => {
+            resolve_index(idx, seq.len()).map(|idx| seq.remove(idx))
+        }
+        _ => None,
+    };
+    removed.ok_or_else(|| error!(PathNotFound: "No entry to remove at the path `{path}`"))
+}
+
+/// Returns the value at the path, failing if it does not exist.
+pub fn get_value<'a>(obj: &'a Value, path: &str) -> Result<&'a Value> {
+    find_value(obj, path)?.ok_or_else(|| error!(PathNotFound: "No value at the path `{path}`"))
+}
+
+/// Returns the value at the path or `None` if it does not exist.
+pub fn find_value<'a>(obj: &'a Value, path: &str) -> Result<Option<&'a Value>> {
+    let mut current_obj = obj;
+    for segment in parse_path(path)? {
+        let next = match segment {
+            PathSegment::Index(idx) => match current_obj {
+                Value::Sequence(seq) => resolve_index(idx, seq.len()).map(|i| &seq[i]),
+                obj => obj.get(Value::from(idx)),
+            },
+            PathSegment::Wildcard => return Err(wildcard_error(path)),
+            PathSegment::Key(key) => current_obj.get(&key),
+        };
+        match next {
+            Some(next) => current_obj = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(current_obj))
+}
+
+fn require_all_non_null(obj: &Value, paths: &[String]) -> Result<()> {
+    let mut failures = vec![];
+    for path in paths {
+        match find_value(obj, path)? {
+            None => failures.push(format!("  {path} (absent)")),
+            Some(v) if v.is_null() => failures.push(format!("  {path} (null)")),
+            Some(_) => {}
+        }
+    }
+    if !failures.is_empty() {
+        bail!(
+            "Required values are null or absent:\n{}",
+            failures.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_atomically_replaces_longer_content() {
+        let dir = std::env::temp_dir().join(format!("xyaml-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("output.yaml");
+        let long = "a: 1\nb: 2\nc: 3\nd: 4\n";
+        let short = "a: 1\n";
+        write_atomically(&path, long.as_bytes(), None).unwrap();
+        write_atomically(&path, short.as_bytes(), None).unwrap();
+        let content = std::fs::read_to_string(&path);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(content.unwrap(), short);
+    }
+}
@@ -1,31 +1,175 @@
+use clap::parser::ValueSource;
 use clap::Arg;
 use clap::ArgAction;
 use clap::Command;
 use serde_yaml::Value;
-use std::collections::HashMap;
 use std::fs::File;
-use std::fs::OpenOptions;
 use std::io::Write;
 use std::io::{self, Read};
+use std::path::Path;
 use std::path::PathBuf;
+use std::process::Child;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+use xyaml::check::Check;
+use xyaml::checksum;
+use xyaml::merge;
+use xyaml::output;
+use xyaml::output::NullStyle;
+use xyaml::output::OutputEncoding;
+use xyaml::output::OutputFormat;
+use xyaml::regex;
+use xyaml::warn;
+use xyaml::Config;
+use xyaml::InputFormat;
+use xyaml::Operation;
+use xyaml::UndefinedVars;
+use xyaml::ValueHint;
+use xyaml::XyamlError;
 
 #[macro_export]
 macro_rules! fail {
+    ( $kind:ident: $($msg:expr),* ) => {{
+        $crate::exit_with(
+            xyaml::XyamlError::new(format!($($msg),*)).with_kind(xyaml::ErrorKind::$kind),
+        )
+    }};
     ( $($msg:expr),* ) => {{
-        eprintln!($($msg),*);
+        $crate::print_error(&format!($($msg),*));
         std::process::exit(1);
     }}
 }
 
-struct Config {
-    require_null: bool,
-    replacements: Vec<(String, String)>,
-    env_substitutions: Vec<String>,
-    input: Option<PathBuf>,
+/// Whether the errors are printed as JSON objects, see `--error-format`.
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// The exit code with `--fail-if-unchanged` if nothing changed, distinct from
+/// the codes of the failures.
+const UNCHANGED_EXIT_CODE: i32 = 8;
+
+/// Prints the error message to <stderr> in the `--error-format`.
+///
+/// In the JSON format the first lines of the message are the `error` and the
+/// `name=value` detail lines, e.g. `  path=`[db, host]``, become the fields of
+/// the object. The `error=` detail, the cause of the failure, becomes `cause`.
+fn print_error(message: &str) {
+    if !JSON_ERRORS.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+        return;
+    }
+    let mut error = vec![];
+    let mut details: Vec<(String, Vec<&str>)> = vec![];
+    for line in message.lines() {
+        let detail = line.trim_start().split_once('=').filter(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+        });
+        match (detail, details.last_mut()) {
+            (Some((name, value)), _) => {
+                let name = if name == "error" { "cause" } else { name };
+                details.push((name.to_string(), vec![value]));
+            }
+            (None, Some((_, value))) => value.push(line),
+            (None, None) => error.push(line),
+        }
+    }
+    let mut fields = vec![("error".to_string(), error.join("\n"))];
+    for (name, value) in details {
+        let value = value.join("\n");
+        let value = match value.strip_prefix('`').and_then(|v| v.strip_suffix('`')) {
+            Some(quoted) => quoted.to_string(),
+            None => value,
+        };
+        fields.push((name, value));
+    }
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}: {}",
+                output::double_quote(name),
+                output::double_quote(value)
+            )
+        })
+        .collect();
+    eprintln!("{{{}}}", fields.join(", "));
+}
+
+/// Prints the error like `fail!` and exits with the code of its kind.
+fn exit_with(e: XyamlError) -> ! {
+    print_error(e.message());
+    std::process::exit(e.kind().exit_code());
+}
+
+/// Matches the clap arguments reporting the parse errors in the
+/// `--error-format`.
+fn get_matches(args: &[std::ffi::OsString]) -> clap::ArgMatches {
+    command().try_get_matches_from(args).unwrap_or_else(|e| {
+        if !e.use_stderr() || !JSON_ERRORS.load(Ordering::Relaxed) {
+            e.exit();
+        }
+        // The usage and the tips follow the message after a blank line.
+        let message = e.to_string();
+        let message = message.split("\n\n").next().unwrap_or_default();
+        print_error(message.strip_prefix("error: ").unwrap_or(message));
+        std::process::exit(e.exit_code());
+    })
+}
+
+mod exec_all;
+mod profile;
+
+/// A process to run after the transformation.
+struct ExecCommand {
+    path: PathBuf,
+    args: Vec<String>,
+    /// Whether the result is written into the stdin of the process.
+    pipe_yaml: bool,
+    /// The file connected to the stdin of the process instead of the result.
+    stdin: Option<PathBuf>,
+}
+
+/// The transformation config together with the input, the output and the
+/// processes to `exec` afterwards.
+struct Cli {
+    config: Config,
+    inputs: Vec<PathBuf>,
     output: Option<PathBuf>,
-    exec: Option<PathBuf>,
-    subst_args_from_env: bool,
-    exec_args: Vec<String>,
+    /// The Unix permissions of the output file.
+    output_mode: Option<u32>,
+    output_encoding: OutputEncoding,
+    in_place: bool,
+    /// The inputs replace the top-level entries of the previous ones instead
+    /// of being deep-merged.
+    shallow_merge: bool,
+    dry_run: bool,
+    fail_if_unchanged: bool,
+    preserve_comments: bool,
+    /// The JSON Schema the result is validated against.
+    schema: Option<Value>,
+    /// The checks of the `check` subcommand, the inputs are not transformed
+    /// then.
+    checks: Option<Vec<Check>>,
+    watch: bool,
+    watch_debounce: Duration,
+    expand_anchors: bool,
+    /// The processes run one after another, the chain stops at the first one
+    /// that fails.
+    exec: Vec<ExecCommand>,
+    exec_yaml_env_prefix: Option<String>,
+    /// Whether the variables of `exec_yaml_env_prefix` replace the inherited
+    /// ones of the same names.
+    exec_yaml_env_override: bool,
+    /// Whether the processes get only the variables of `exec_yaml_env_prefix`
+    /// instead of the inherited environment.
+    exec_env_clear: bool,
+    exec_timeout: Option<Duration>,
+    exec_pid_file: Option<PathBuf>,
+    exec_log_command: bool,
+    exec_cwd_from_yaml: Option<String>,
+    exec_on_success: Option<(PathBuf, Vec<String>)>,
+    exec_on_failure: Option<(PathBuf, Vec<String>)>,
 }
 
 fn wrap_at(s: &str, at: usize) -> String {
@@ -47,50 +191,688 @@ fn wrap_help(s: &str) -> String {
     wrap_at(s, 70)
 }
 
-fn config() -> Config {
-    let matches = Command::new("xyaml - YAML configuration transformer")
+fn command() -> Command {
+    Command::new("xyaml - YAML configuration transformer")
         .author("SUPREMATIC Technology Arts GmbH")
+        .after_long_help(wrap_help("Exit codes: 0 on success, 1 on other failures, 2 on invalid arguments, 3 on invalid YAML, JSON or TOML input or values, 4 on missing paths, 5 on missing environment variables, 6 on values of wrong types, 7 on file read and write failures, 8 if nothing changed with `--fail-if-unchanged`."))
+        .args_override_self(true)
         .args([
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("Prepend the arguments of the named profile")
+                .long_help(wrap_help("Prepend the arguments of the named profile to the command line arguments. Profiles are read from `.xyaml.yaml` in the current directory or `~/.config/xyaml/profiles.yaml` and may inherit from each other with `extends: <NAME>`. Single-valued arguments given on the command line override the ones from the profile."))
+                .action(ArgAction::Append)
+                .num_args(1),
             Arg::new("require-null")
                 .long("require-null")
                 .help("Require the replaced value to be `null`")
                 .num_args(0),
+            Arg::new("require-key")
+                .long("require-key")
+                .value_name("PATH")
+                .help("Require the value at the path to be present and not `null` in the input")
+                .long_help(wrap_help("Require the value at the specified path to be present and not `null` in the input YAML before any changes are made. All failing paths are reported at once."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("create")
+                .long("create")
+                .visible_alias("create-path")
+                .help("Create the missing entries along the paths of the set values")
+                .long_help(wrap_help("Create the missing entries along the paths of the set values instead of failing: missing mapping keys are inserted, sequences are extended with `null` up to the index, and `null` values become mappings or sequences."))
+                .num_args(0),
+            Arg::new("env-file")
+                .long("env-file")
+                .value_name("FILE")
+                .help("Load the environment variables from the dotenv <FILE>")
+                .long_help(wrap_help("Parse the dotenv <FILE> and set its variables in the environment before any other option reads it. The variables already set in the environment are not overridden unless `--env-file-override` is passed. Supports comments, `export` prefixes, quoted and multi-line values."))
+                .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("env-file-override")
+                .long("env-file-override")
+                .help("Override the variables already set in the environment with `--env-file`")
+                .num_args(0),
             Arg::new("env-values")
                 .long("env-values")
-                .help("The values provided to `--set` are names of environment variables")
+                .help("The values provided to `--set` and its typed variants are names of environment variables")
                 .num_args(0),
+            Arg::new("values-file")
+                .long("values-file")
+                .value_name("FILE")
+                .help("Set the values of the YAML mapping <FILE> at their paths")
+                .long_help(wrap_help("Set every value of the nested YAML mapping <FILE> at the path of its keys, e.g. `{database: {host: db}}` is equivalent to `--set '[database, host]' db`. The values files are applied before all other operations, so that the command line overrides them."))
+                .action(ArgAction::Append)
+                .num_args(1),
             Arg::new("replacements")
                 .long("set")
+                .visible_alias("set-yaml")
                 .value_names(["PATH", "VALUE"])
                 .help("Set the value at the specified path")
+                .long_help(wrap_help("Set the value at the specified path. The value is parsed as YAML, so that e.g. `1.20` becomes the float `1.2` and `true` a boolean, see `--set-string` and the other typed variants. Paths are either YAML sequences of keys and `[N]` indexes, e.g. `[servers, [0], host]`, or dot-separated keys and indexes, e.g. `servers.0.host` or `servers[0].host`, where `\\.` and `\\[` are a literal dot and bracket. Negative indexes count from the end of a sequence. A `*` segment matches all entries of a mapping or a sequence, e.g. `[spec, containers, *, image]`."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-string")
+                .long("set-string")
+                .value_names(["PATH", "VALUE"])
+                .help("Set the value at the specified path as a string")
+                .long_help(wrap_help("Set the value at the specified path as a string as is, without parsing it as YAML, e.g. `5432` becomes `'5432'`."))
                 .action(ArgAction::Append)
                 .num_args(2),
+            Arg::new("set-int")
+                .long("set-int")
+                .value_names(["PATH", "VALUE"])
+                .help("Set the value at the specified path as an integer")
+                .long_help(wrap_help("Set the value at the specified path as an integer. Fails if <VALUE> is not a decimal integer."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-float")
+                .long("set-float")
+                .value_names(["PATH", "VALUE"])
+                .help("Set the value at the specified path as a float")
+                .long_help(wrap_help("Set the value at the specified path as a float, e.g. `1.20` stays `1.2` and `1` becomes `1.0`. Fails if <VALUE> is not a number."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-bool")
+                .long("set-bool")
+                .value_names(["PATH", "VALUE"])
+                .help("Set the value at the specified path as a boolean")
+                .long_help(wrap_help("Set the value at the specified path as a boolean. Fails if <VALUE> is not `true` or `false`."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-null")
+                .long("set-null")
+                .value_name("PATH")
+                .help("Set `null` at the specified path")
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("set-default")
+                .long("set-default")
+                .value_names(["PATH", "VALUE"])
+                .help("Set the value at the path unless it is already set")
+                .long_help(wrap_help("Parse <VALUE> as YAML and set it at the specified path only if the key is missing or its value is `null`, otherwise keep the current value. Missing parent entries are only created with `--create`."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-join")
+                .long("set-join")
+                .value_names(["PATH", "SEPARATOR", "VALUE"])
+                .help("Join the values with <SEPARATOR> and set the string at the path")
+                .long_help(wrap_help("Join the <VALUE>s with <SEPARATOR> and set the resulting string at the specified path. A value starting with `@` is a path reference, e.g. `@[db, host]`, and is replaced with the current value at that path. Use `@@` to start a value with a literal `@`."))
+                .action(ArgAction::Append)
+                .num_args(3..),
+            Arg::new("set-from-checksum")
+                .long("set-from-checksum")
+                .value_names(["PATH", "ALGO", "FILE"])
+                .help("Set the hex-encoded hash of the <FILE> content at the path")
+                .long_help(wrap_help("Compute the hash of the <FILE> content and set the hex-encoded result at the specified path. <ALGO> is one of `sha256`, `sha1` or `md5`."))
+                .action(ArgAction::Append)
+                .num_args(3),
+            Arg::new("copy-subtree")
+                .long("copy-subtree")
+                .value_names(["SRC_PATH", "DST_FILE", "DST_PATH"])
+                .help("Copy the resulting value at <SRC_PATH> into another YAML file")
+                .long_help(wrap_help("Copy the value at <SRC_PATH> of the resulting YAML to <DST_PATH> of the YAML file <DST_FILE>. The rest of <DST_FILE> is left unchanged and the file is replaced atomically."))
+                .action(ArgAction::Append)
+                .num_args(3),
+            Arg::new("set-if-regex")
+                .long("set-if-regex")
+                .value_names(["PATH", "PATTERN", "VALUE"])
+                .help("Set the value if the current one matches the regex <PATTERN>")
+                .long_help(wrap_help("Set the value at the specified path only if the current scalar value as a whole matches the regular expression <PATTERN>. The value is left unchanged otherwise."))
+                .action(ArgAction::Append)
+                .num_args(3),
+            Arg::new("replace-in-keys")
+                .long("replace-in-keys")
+                .value_names(["PATH", "OLD", "NEW"])
+                .help("Replace <OLD> with <NEW> in the keys of the mapping at the path")
+                .long_help(wrap_help("Replace all occurrences of the substring <OLD> with <NEW> in the string keys of the mapping at the specified path. Fails if a replacement results in a duplicate key."))
+                .action(ArgAction::Append)
+                .num_args(3),
+            Arg::new("set-from-env-file")
+                .long("set-from-env-file")
+                .value_names(["FILE", "PATH"])
+                .help("Deep-merge the variables of the dotenv <FILE> into the path")
+                .long_help(wrap_help("Parse the dotenv <FILE> and deep-merge its variables as a mapping of string values into the value at the specified path. Supports comments, `export` prefixes, quoted and multi-line values."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-integer-range")
+                .long("set-integer-range")
+                .value_names(["PATH", "START", "END", "STEP"])
+                .help("Set the sequence of integers from <START> to <END> (exclusive)")
+                .long_help(wrap_help("Set the sequence of integers from <START> to <END> (exclusive) with the increment <STEP> at the specified path. <STEP> is optional and defaults to 1, it may be negative to count down."))
+                .action(ArgAction::Append)
+                .allow_negative_numbers(true)
+                .num_args(3..=4),
+            Arg::new("unset-if-default")
+                .long("unset-if-default")
+                .value_names(["PATH", "DEFAULT_VALUE"])
+                .help("Remove the entry at the path if its value equals <DEFAULT_VALUE>")
+                .long_help(wrap_help("Remove the entry at the specified path from its parent if its value equals <DEFAULT_VALUE> parsed as YAML. Nothing is done if the path does not exist."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("inject-env")
+                .long("inject-env")
+                .value_names(["PATH", "ENV_VAR_LIST_PATH"])
+                .help("Set the mapping of the environment variables listed in the document")
+                .long_help(wrap_help("Read the sequence of environment variable names at <ENV_VAR_LIST_PATH> and set the mapping of those names to their values at the specified path. Fails if any of the variables is not set."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-from-file")
+                .long("set-from-file")
+                .value_names(["PATH", "FILE"])
+                .help("Set the content of the <FILE> as a string at the path")
+                .long_help(wrap_help("Read the <FILE> and set its content as is as a string at the specified path, e.g. a certificate or a script. Fails if the content is not valid UTF-8."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-file")
+                .long("set-file")
+                .value_name("FILE")
+                .help("Set the values of the path and value line pairs of the <FILE>")
+                .long_help(wrap_help("Read the alternating <PATH> and <VALUE> lines of the <FILE> and set the values parsed as YAML as with `--set`. A value may span multiple lines if it is a block scalar, e.g. `|` followed by the indented lines, or a quoted string. Blank lines and `#` comments between the pairs are skipped."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("set-from-stdin")
+                .long("set-from-stdin")
+                .help("Set the values of the path and value line pairs read from <stdin>")
+                .long_help(wrap_help("Read the path and value line pairs from <stdin> as with `--set-file`. Requires `--input`, since the input cannot be read from <stdin> then."))
+                .requires("input")
+                .num_args(0),
+            Arg::new("set-from-yaml-file")
+                .long("set-from-yaml-file")
+                .value_names(["PATH", "FILE"])
+                .help("Set the content of the YAML <FILE> at the path")
+                .long_help(wrap_help("Read the <FILE>, parse it as YAML and set the resulting value at the specified path."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("set-from-file-lines")
+                .long("set-from-file-lines")
+                .value_names(["PATH", "FILE"])
+                .help("Set the sequence of the <FILE> lines at the path")
+                .long_help(wrap_help("Read the <FILE> and set the sequence of its lines as strings at the specified path. Empty lines are included unless `--skip-empty-lines` is given."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("skip-empty-lines")
+                .long("skip-empty-lines")
+                .help("Skip the empty lines in `--set-from-file-lines`")
+                .num_args(0),
+            Arg::new("set-from-query")
+                .long("set-from-query")
+                .value_names(["PATH", "SRC_FILE", "SRC_PATH"])
+                .help("Set the value at <SRC_PATH> of the YAML <SRC_FILE> at the path")
+                .long_help(wrap_help("Read the YAML <SRC_FILE>, take the value at <SRC_PATH> in it and set it at the specified path. Fails if <SRC_PATH> does not exist in <SRC_FILE>."))
+                .action(ArgAction::Append)
+                .num_args(3),
+            Arg::new("force-string")
+                .long("force-string")
+                .value_name("PATH")
+                .help("Convert the value at the path to a string")
+                .long_help(wrap_help("Replace the value at the specified path with its YAML representation as a string if it is not a string already, e.g. `42` becomes `\"42\"` and `null` becomes `\"null\"`."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("delete")
+                .long("delete")
+                .visible_alias("unset")
+                .value_name("PATH")
+                .help("Remove the entry at the path from its parent")
+                .long_help(wrap_help("Remove the key at the specified path from its parent mapping, or the element from its parent sequence shifting the following elements. Fails if the path does not exist, unless `--delete-missing-ok` is given, or if the value is not `null` with `--require-null`."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("delete-missing-ok")
+                .long("delete-missing-ok")
+                .help("Ignore the missing paths of `--delete` instead of failing")
+                .num_args(0),
+            Arg::new("append")
+                .long("append")
+                .value_names(["PATH", "VALUE"])
+                .help("Append the value to the sequence at the path")
+                .long_help(wrap_help("Parse <VALUE> as YAML and append it to the sequence at the specified path. Fails if the value at the path is not a sequence."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("copy")
+                .long("copy")
+                .value_names(["SRC_PATH", "DST_PATH"])
+                .help("Copy the value at <SRC_PATH> to <DST_PATH>")
+                .long_help(wrap_help("Set the value at <SRC_PATH> at <DST_PATH> of the same document. Fails if <SRC_PATH> does not exist, or if the value at <DST_PATH> is not `null` with `--require-null`."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("replace-pattern")
+                .long("replace-pattern")
+                .value_names(["REGEX", "REPLACEMENT"])
+                .help("Replace the matches of <REGEX> in all string values")
+                .long_help(wrap_help("Replace every match of <REGEX> with <REPLACEMENT> in all string values of the document. <REPLACEMENT> may refer to the capture groups as `$1`, `${1}`, `$name` or `${name}`; `$$` is a literal `$`. Applied in the order given along with the other operations, before `--env-subst`."))
+                .action(ArgAction::Append)
+                .num_args(2),
+            Arg::new("repeat-last")
+                .long("repeat-last")
+                .value_name("N")
+                .help("Re-apply the preceding <N> operations")
+                .long_help(wrap_help("Re-apply the <N> operations preceding this flag on the command line `--repeat-count` more times."))
+                .value_parser(clap::value_parser!(usize))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("repeat-count")
+                .long("repeat-count")
+                .value_name("M")
+                .help("How many times `--repeat-last` re-applies the operations")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .num_args(1),
+            Arg::new("require-all-non-null")
+                .long("require-all-non-null")
+                .value_name("PATH")
+                .help("Require the values at the paths to be present and not `null`")
+                .long_help(wrap_help("Require the values at all of the specified paths to be present and not `null` in the resulting YAML. All failing paths are reported at once."))
+                .action(ArgAction::Append)
+                .num_args(1..),
+            Arg::new("merge-from-exec")
+                .long("merge-from-exec")
+                .value_name("CMD")
+                .help("Deep-merge the YAML printed by <CMD> into the input")
+                .long_help(wrap_help("Run <CMD> with `sh -c`, parse its stdout as YAML and deep-merge it into the input YAML before the values are set. Mappings are merged recursively, other values are replaced, see also `--merge-sequences`. Fails if <CMD> exits with a non-zero code."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("merge")
+                .long("merge")
+                .value_name("FILE")
+                .help("Deep-merge the YAML <FILE> into the result")
+                .long_help(wrap_help("Parse the YAML <FILE> and deep-merge it into the YAML after the values are set and before the env substitutions. Mappings are merged recursively, other values are replaced, see also `--merge-sequences`. Multiple files are merged in the order they are specified."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("merge-sequences")
+                .long("merge-sequences")
+                .value_name("MODE")
+                .help("How the overlay sequences are deep-merged into the base sequences")
+                .long_help(wrap_help("How the overlay sequences are deep-merged into the base sequences with `--merge` and `--merge-from-exec`: `replace` replaces the base sequences, `append` appends the overlay elements to them, `by-name` deep-merges the overlay mappings into the base mappings with the same `name` and appends the others, `by-index` deep-merges the overlay elements into the base elements at the same indexes and appends the extra ones. See also `--merge-strategy`."))
+                .value_parser(merge::SequenceMerge::NAMES)
+                .conflicts_with("merge-sequences-append")
+                .num_args(1),
+            Arg::new("merge-strategy")
+                .long("merge-strategy")
+                .value_name("PATH:MODE")
+                .help("How the sequences at the path are deep-merged")
+                .long_help(wrap_help("How the overlay sequences at the path are deep-merged into the base sequences, overriding `--merge-sequences` for that path, e.g. `--merge-strategy '[spec, containers]:by-name'`. The path may have `*` segments. If several paths match, the last one given wins."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("merge-sequences-append")
+                .long("merge-sequences-append")
+                .help("Same as `--merge-sequences append`")
+                .long_help(wrap_help("Append the elements of the overlay sequences to the base sequences when deep-merging with `--merge` or `--merge-from-exec` instead of replacing the base sequences. Same as `--merge-sequences append`."))
+                .num_args(0),
             Arg::new("env-substitutions")
                 .long("env-subst")
-                .value_name("VAR")
+                .value_name("VAR[=DEFAULT]")
                 .help("Repace <VAR> placeholder with its environment variable value")
-                .long_help(wrap_help("Repace the placeholder with the name of <VAR> with the corresponding environment variable value. The env substitutions happen after the path replacements."))
+                .long_help(wrap_help("Repace the `{{VAR}}` placeholders with the corresponding environment variable value. A value consisting of a single placeholder is replaced with the variable value parsed as YAML, placeholders embedded in a longer string are replaced in the string, which stays a string unless `--typed-env-subst` is given. `{{VAR:-DEFAULT}}` is replaced with `DEFAULT` and `{{VAR:?MESSAGE}}` fails with `MESSAGE` if the variable is unset or empty. Placeholders in the mapping keys are replaced in the key strings. The env substitutions happen after the path replacements."))
                 .action(ArgAction::Append)
                 .num_args(1),
+            Arg::new("env-subst-all")
+                .long("env-subst-all")
+                .help("Substitute the placeholders of all environment variables")
+                .long_help(wrap_help("Substitute every `{{VAR}}` placeholder in the document whose name is a valid environment variable name as with `--env-subst`. With `--env-subst-prefix` only the names starting with one of the prefixes are substituted. The placeholders of the unset variables are handled according to `--env-subst-undefined` unless they all have the `:-` or `:?` modifiers."))
+                .num_args(0),
+            Arg::new("env-subst-undefined")
+                .long("env-subst-undefined")
+                .value_name("POLICY")
+                .help("How the placeholders of the unset variables are handled with `--env-subst-all`")
+                .long_help(wrap_help("How the placeholders of the unset variables are handled with `--env-subst-all`: `error` fails, `keep` leaves them as is, `empty` substitutes empty values."))
+                .value_parser(UndefinedVars::NAMES)
+                .default_value("error")
+                .requires("env-subst-all")
+                .num_args(1),
+            Arg::new("env-subst-prefix")
+                .long("env-subst-prefix")
+                .visible_alias("env-prefix")
+                .value_name("PREFIX")
+                .help("Substitute the placeholders of all environment variables starting with <PREFIX>")
+                .long_help(wrap_help("Substitute the `{{VAR}}` placeholders of all environment variables whose names start with <PREFIX> as with `--env-subst`, e.g. `{{MYAPP_DATABASE_HOST}}` with `--env-subst-prefix MYAPP_`. See also `--env-subst-prefix-strip`."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("env-subst-prefix-strip")
+                .long("env-subst-prefix-strip")
+                .help("Strip <PREFIX> from the placeholder names of `--env-subst-prefix`")
+                .long_help(wrap_help("Strip <PREFIX> from the placeholder names of `--env-subst-prefix`, e.g. substitute `{{DATABASE_HOST}}` with the value of `MYAPP_DATABASE_HOST` with `--env-subst-prefix MYAPP_`."))
+                .requires("env-subst-prefix")
+                .num_args(0),
+            Arg::new("typed-env-subst")
+                .long("typed-env-subst")
+                .help("Parse the strings with embedded placeholders as YAML after the env substitution")
+                .long_help(wrap_help("Parse the strings with the placeholders embedded by `--env-subst` and `--env-subst-prefix` as YAML after the substitution, e.g. `{{HOST}}:{{PORT}}` remains a string while `{{MAJOR}}.{{MINOR}}` may become a float. The strings that are not valid YAML stay strings. A value consisting of a single placeholder is always parsed as YAML."))
+                .num_args(0),
+            Arg::new("placeholder-start")
+                .long("placeholder-start")
+                .value_name("DELIMITER")
+                .help("The start delimiter of the env substitution placeholders")
+                .long_help(wrap_help("The start delimiter of the placeholders of `--env-subst`, `--env-subst-prefix` and `exec --subst-args-with-env`, e.g. `${` with `--placeholder-end }` for `${VAR}` placeholders if the YAML uses `{{` for other purposes, such as templates."))
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .default_value("{{")
+                .num_args(1),
+            Arg::new("placeholder-end")
+                .long("placeholder-end")
+                .value_name("DELIMITER")
+                .help("The end delimiter of the env substitution placeholders")
+                .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                .default_value("}}")
+                .num_args(1),
+            Arg::new("validate-env-subst-coverage")
+                .long("validate-env-subst-coverage")
+                .help("Warn about `--env-subst` variables without placeholders")
+                .long_help(wrap_help("Warn about every `--env-subst` variable that had no placeholder to substitute in the document, e.g. due to a misspelled placeholder name."))
+                .num_args(0),
+            Arg::new("strict")
+                .long("strict")
+                .help("Fail instead of warning on `--validate-env-subst-coverage` findings")
+                .num_args(0),
+            Arg::new("error-format")
+                .long("error-format")
+                .value_name("FORMAT")
+                .help("The format of the error messages")
+                .long_help(wrap_help("The format of the error messages printed to <stderr>: `text`, or `json` for one JSON object per error, e.g. `{\"error\": \"No key `host`\", \"path\": \"[db, host]\", ...}` with the details of the error as the other fields. Applies to the command line parsing errors too."))
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .num_args(1),
+            Arg::new("verbose")
+                .long("verbose")
+                .help("Print every change to <stderr>")
+                .long_help(wrap_help("Print the path, the old and the new value of every change made by the operations and the env substitutions to <stderr>, e.g. `[xyaml] set [database, host]: old -> new`."))
+                .num_args(0),
+            Arg::new("quiet")
+                .long("quiet")
+                .help("Do not print the warnings to <stderr>")
+                .long_help(wrap_help("Do not print the warnings to <stderr>, e.g. about the expanded anchors or the values skipped by the output formats. The errors are still printed."))
+                .conflicts_with("verbose")
+                .num_args(0),
             Arg::new("input")
                 .long("input")
                 .value_name("FILE")
                 .help("Read the YAML from <FILE> instead of <stdin>")
+                .long_help(wrap_help("Read the YAML from <FILE> instead of <stdin>. If given multiple times, the files are deep-merged in order before the values are set, so that the later files override the earlier ones, see also `--merge-sequences`."))
                 .value_parser(clap::value_parser!(PathBuf))
+                .action(ArgAction::Append)
                 .num_args(1),
             Arg::new("output")
                 .long("output")
                 .value_name("FILE")
                 .help("Write the result into the <FILE> instead of printing to <stdout>")
+                .long_help(wrap_help("Write the result into the <FILE> instead of printing to <stdout>. The result is written into a temporary file next to it first and renamed over it, so that the file is never left partially written. The file keeps its permissions."))
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("output-encoding")
+                .long("output-encoding")
+                .value_name("ENCODING")
+                .help("The character encoding of the output file")
+                .long_help(wrap_help("The character encoding of the `--output` or the `--in-place` file: `utf-8`, `utf-8-bom` for UTF-8 with the byte order mark, `utf-16-le` or `utf-16-be`, the UTF-16 encodings start with the byte order mark. The result printed to <stdout> is always UTF-8."))
+                .value_parser(OutputEncoding::NAMES)
+                .default_value("utf-8")
+                .num_args(1),
+            Arg::new("output-file-permissions")
+                .long("output-file-permissions")
+                .value_name("MODE")
+                .help("Set the Unix permissions of the output file, e.g. `0600`")
+                .long_help(wrap_help("Set the Unix permissions of the `--output` or the `--in-place` file to the octal <MODE>, e.g. `0600` for secrets. The permissions are set on the temporary file before it is renamed over the output file. Ignored with a warning on other platforms."))
+                .num_args(1),
+            Arg::new("in-place")
+                .long("in-place")
+                .short('i')
+                .help("Write the result back into the `--input` file")
+                .long_help(wrap_help("Write the result back into the `--input` file instead of printing to <stdout>. The result is written into a temporary file next to it first and renamed over it, so that the file is never left partially written."))
+                .requires("input")
+                .conflicts_with("output")
+                .num_args(0),
+            Arg::new("validate-schema")
+                .long("validate-schema")
+                .value_name("SCHEMA_FILE")
+                .help("Validate the result against the JSON Schema")
+                .long_help(wrap_help("Validate the transformed documents against the JSON or YAML JSON Schema file before writing the result or running the exec command, and fail reporting the paths and the constraints of all violations. Supports the common draft 7 and 2020-12 keywords, such as `type`, `properties`, `required`, `items`, `enum`, `pattern`, the numeric and the length limits, the combinators and the `#/...` `$ref`s."))
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Print the diff of the changes to <stderr> instead of writing the result")
+                .long_help(wrap_help("Print the line diff between the input and the result to <stderr> in the unified format, or `No changes` if they have the same lines. Nothing is written into the output and the exec command is not run."))
+                .num_args(0),
+            Arg::new("fail-if-unchanged")
+                .long("fail-if-unchanged")
+                .help("Exit with the code 8 if the transformations changed nothing")
+                .long_help(wrap_help("Exit with the code 8 without writing the result or running the exec command if the transformations left the input documents unchanged, e.g. if the set values were already there. The input and the result are compared as YAML values, so that the formatting does not matter."))
+                .conflicts_with("watch")
+                .num_args(0),
+            Arg::new("watch")
+                .long("watch")
+                .help("Re-apply the transformations whenever the `--input` file changes")
+                .long_help(wrap_help("Keep running and re-apply the transformations whenever the `--input` file changes, including when it is replaced. The exec process is killed and started again on every change. The errors are reported without exiting."))
+                .requires("input")
+                .conflicts_with_all(["in-place", "dry-run"])
+                .num_args(0),
+            Arg::new("debounce-ms")
+                .long("debounce-ms")
+                .value_name("N")
+                .help("Wait until the input file has not changed for <N> milliseconds with `--watch`")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("100")
+                .num_args(1),
+            Arg::new("no-yaml-tags")
+                .long("no-yaml-tags")
+                .help("Strip the `!!TAG` type annotations from the input YAML")
+                .long_help(wrap_help("Strip all `!!TAG` type annotations, e.g. `!!str` or `!!int`, from the input YAML before parsing it, so that the types are inferred from the values."))
+                .num_args(0),
+            Arg::new("expand-anchors")
+                .long("expand-anchors")
+                .visible_alias("no-aliases")
+                .help("Expand the YAML anchors and aliases in the output")
+                .long_help(wrap_help("Expand all YAML anchors and aliases, i.e. output a copy of the anchored value in place of every alias. This is always done, the flag only makes it explicit and silences the warning printed when the input contains anchors."))
+                .num_args(0),
+            Arg::new("trim-strings")
+                .long("trim-strings")
+                .help("Strip the leading and trailing whitespace from all string values")
+                .num_args(0),
+            Arg::new("trim-string-values-at")
+                .long("trim-string-values-at")
+                .value_name("PATH")
+                .help("Strip the leading and trailing whitespace from the string values at the path")
+                .long_help(wrap_help("Strip the leading and trailing whitespace from all string values of the subtree at the specified path."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("trim-keys")
+                .long("trim-keys")
+                .help("Strip the leading and trailing whitespace from all mapping keys")
+                .num_args(0),
+            Arg::new("sort-keys")
+                .long("sort-keys")
+                .help("Sort the keys of all mappings")
+                .long_help(wrap_help("Sort the keys of all mappings of the resulting YAML lexicographically, including the nested ones."))
+                .num_args(0),
+            Arg::new("sort-keys-at")
+                .long("sort-keys-at")
+                .value_name("PATH")
+                .help("Sort the keys of the mapping at the path")
+                .long_help(wrap_help("Sort the keys of the mapping at the specified path lexicographically. Nested mappings are left unchanged."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("input-format")
+                .long("input-format")
+                .value_name("FORMAT")
+                .help("The format of the input")
+                .long_help(wrap_help("The format of the input. `toml` tables become mappings, dates and times become strings. Defaults to the format of the first `--input` file extension, `.json`, `.toml`, `.yaml` or `.yml`, or `yaml`."))
+                .value_parser(InputFormat::NAMES)
+                .default_value("yaml")
+                .num_args(1),
+            Arg::new("output-format")
+                .long("output-format")
+                .value_name("FORMAT")
+                .help("The format of the result")
+                .long_help(wrap_help("The format of the result. `aws-ssm` emits an `aws ssm put-parameter` command for every scalar leaf of the resulting YAML. `kubernetes-configmap` wraps the scalar leaves, with the paths joined with `.` as the keys, into a ConfigMap manifest. `json` emits the document as pretty-printed JSON. `shell` emits a `NAME=\"value\"` assignment for every scalar leaf of the nested mappings, with the uppercased keys joined with `_` as the names, e.g. for `eval`. `toml` emits the document as TOML, failing on `null` values. Defaults to the format of the output file extension, `.json`, `.toml`, `.yaml` or `.yml`, or `yaml`."))
+                .value_parser(OutputFormat::NAMES)
+                .default_value("yaml")
+                .num_args(1),
+            Arg::new("path-comments")
+                .long("path-comments")
+                .help("Annotate each scalar value with its path as a YAML comment")
+                .num_args(0),
+            Arg::new("preserve-comments")
+                .long("preserve-comments")
+                .help("Keep the comments of the input in the resulting YAML")
+                .long_help(wrap_help("Keep the comments of the input in the resulting YAML on a best-effort basis: the comment lines preceding the block mapping keys and the sequence entries and the comments at the ends of their lines are attached to their paths and emitted at the same paths of the result, as are the blank lines between the entries. The comments within flow collections and multi-line values and the ones of the deleted entries are dropped. Only the comments and the blank lines are kept: the result is serialized anew, so the rest of the formatting is normalized, e.g. the quoting of the strings and the indentation of the sequences."))
+                .conflicts_with_all(["path-comments", "flow-style", "get", "eval-template"])
+                .num_args(0),
+            Arg::new("flow-style")
+                .long("flow-style")
+                .help("Emit the resulting YAML on a single line in the flow style")
+                .conflicts_with("path-comments")
+                .num_args(0),
+            Arg::new("null-style")
+                .long("null-style")
+                .value_name("STYLE")
+                .help("The representation of the null values in the resulting YAML")
+                .long_help(wrap_help("The representation of the null values in the resulting YAML: `null`, `tilde` for `~`, `empty` for no value, e.g. `key:`, or `omit` to drop the mapping entries with the null values. `empty` writes `null` within flow collections."))
+                .value_parser(NullStyle::NAMES)
+                .default_value("null")
+                .num_args(1),
+            Arg::new("ssm-prefix")
+                .long("ssm-prefix")
+                .value_name("PREFIX")
+                .help("The parameter name prefix for `--output-format aws-ssm`")
+                .default_value("")
+                .num_args(1),
+            Arg::new("ssm-secure-paths")
+                .long("ssm-secure-paths")
+                .value_name("GLOB")
+                .help("Emit the matching parameters with `--type SecureString`")
+                .long_help(wrap_help("Emit the parameters whose names (without the prefix, e.g. `db/password`) match the <GLOB> with `--type SecureString`. `*` matches any characters, `?` matches a single one."))
+                .action(ArgAction::Append)
+                .num_args(1),
+            Arg::new("exec-pid-file")
+                .long("exec-pid-file")
+                .value_name("FILE")
+                .help("Write the PID of the `exec` process into <FILE>")
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("exec-log-command")
+                .long("exec-log-command")
+                .help("Print the `exec` command line to <stderr> before running it")
+                .num_args(0),
+            Arg::new("configmap-name")
+                .long("configmap-name")
+                .value_name("NAME")
+                .help("The name of the ConfigMap for `--output-format kubernetes-configmap`")
+                .num_args(1),
+            Arg::new("configmap-namespace")
+                .long("configmap-namespace")
+                .value_name("NS")
+                .help("The namespace of the ConfigMap for `--output-format kubernetes-configmap`")
+                .num_args(1),
+            Arg::new("configmap-key")
+                .long("configmap-key")
+                .value_name("KEY")
+                .help("Put the whole YAML under <KEY> of the ConfigMap data")
+                .long_help(wrap_help("Put the whole resulting YAML under <KEY> of the ConfigMap data instead of adding an entry for every scalar leaf."))
+                .num_args(1),
+            Arg::new("exec-cwd-from-yaml")
+                .long("exec-cwd-from-yaml")
+                .value_name("PATH")
+                .help("Run the `exec` process in the directory set at the path")
+                .long_help(wrap_help("Run the `exec` process in the working directory specified by the string value at the path of the resulting YAML. The directory must exist."))
+                .num_args(1),
+            Arg::new("exec-on-success")
+                .long("exec-on-success")
+                .value_name("CMD")
+                .help("Run <CMD> after the `exec` process exits with zero")
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("exec-on-success-arg")
+                .long("exec-on-success-arg")
+                .value_name("ARG")
+                .help("An argument for the `--exec-on-success` command")
+                .requires("exec-on-success")
+                .action(ArgAction::Append)
+                .allow_hyphen_values(true)
+                .num_args(1),
+            Arg::new("exec-on-failure")
+                .long("exec-on-failure")
+                .value_name("CMD")
+                .help("Run <CMD> after the `exec` process fails")
                 .value_parser(clap::value_parser!(PathBuf))
                 .num_args(1),
+            Arg::new("exec-on-failure-arg")
+                .long("exec-on-failure-arg")
+                .value_name("ARG")
+                .help("An argument for the `--exec-on-failure` command")
+                .requires("exec-on-failure")
+                .action(ArgAction::Append)
+                .allow_hyphen_values(true)
+                .num_args(1),
+            Arg::new("eval-template")
+                .long("eval-template")
+                .value_name("TEMPLATE_FILE")
+                .help("Render <TEMPLATE_FILE> using the resulting YAML as the context")
+                .long_help(wrap_help("Render the Handlebars-style <TEMPLATE_FILE> using the resulting YAML document as the rendering context and output the rendered text instead of the YAML. Supports `{{path.to.value}}`, `{{#each}}`, `{{#if}}`, `{{#unless}}` and `{{#with}}`. As in Handlebars, `{{path.to.value}}` escapes the HTML special characters such as `<` and `&`, while `{{{path.to.value}}}` inserts the value as is."))
+                .value_parser(clap::value_parser!(PathBuf))
+                .num_args(1),
+            Arg::new("document")
+                .long("document")
+                .visible_alias("doc")
+                .value_name("N")
+                .help("Transform only the 0-based <N>th document of a multi-document input")
+                .long_help(wrap_help("Apply the transformations only to the 0-based <N>th of the `---`-separated input documents. The other documents are output unchanged. Without it all documents are transformed."))
+                .value_parser(clap::value_parser!(usize))
+                .num_args(1),
+            Arg::new("select-document")
+                .long("select-document")
+                .value_name("PATH=VALUE")
+                .help("Transform only the documents with <VALUE> at the path")
+                .long_help(wrap_help("Apply the transformations only to the input documents whose value at the path equals <VALUE> parsed as YAML, e.g. `kind=Deployment`. The other documents are output unchanged. Fails if no document matches."))
+                .conflicts_with("document")
+                .num_args(1),
+            Arg::new("get")
+                .long("get")
+                .value_name("PATH")
+                .help("Print the value at the path instead of the document")
+                .long_help(wrap_help("Print the resulting value at the specified path instead of the whole document, for every transformed document. Strings are printed as is, other scalars in their YAML form, mappings and sequences as YAML documents. Fails if the path does not exist."))
+                .conflicts_with("eval-template")
+                .num_args(1),
         ])
         .subcommand(
             Command::new("exec").args([
                 Arg::new("subst-args-with-env")
                     .long("subst-args-with-env")
-                    .help(wrap_help("Substitue the arguments with the corresponding environment variable values."))
+                    .help(wrap_help("Replace the `{{VAR}}` placeholders within the arguments with the corresponding environment variable values, e.g. `--url=http://{{HOST}}/api`."))
+                    .num_args(0),
+                Arg::new("yaml-as-env")
+                    .long("yaml-as-env")
+                    .value_name("PREFIX")
+                    .help(wrap_help("Pass the scalar leaves of the nested mappings of the result to the process as its only `<PREFIX>_KEY_SUBKEY` environment variables, see `--inherit-env`."))
+                    .num_args(1),
+                Arg::new("inherit-env")
+                    .long("inherit-env")
+                    .help(wrap_help("Pass the variables of `--yaml-as-env` in addition to the inherited environment instead of replacing it."))
+                    .requires("yaml-as-env")
+                    .num_args(0),
+                Arg::new("env-from-yaml")
+                    .long("env-from-yaml")
+                    .help(wrap_help("Pass the scalar leaves of the nested mappings of the result to the process as `KEY_SUBKEY` environment variables, e.g. `DATABASE_HOST` for `database.host`. The inherited variables of the same names are kept unless `--env-from-yaml-override` is given."))
+                    .conflicts_with("yaml-as-env")
                     .num_args(0),
+                Arg::new("env-from-yaml-override")
+                    .long("env-from-yaml-override")
+                    .help(wrap_help("Replace the inherited environment variables with the ones of `--env-from-yaml`."))
+                    .requires("env-from-yaml")
+                    .num_args(0),
+                Arg::new("timeout")
+                    .long("timeout")
+                    .value_name("SECONDS")
+                    .help(wrap_help("Terminate the process if it runs longer than <SECONDS>: send `SIGTERM`, then `SIGKILL` if it is still running 5 seconds later, and exit with the code 124. With `--watch` the process is terminated without exiting."))
+                    .value_parser(clap::value_parser!(u64))
+                    .num_args(1),
+                Arg::new("pipe-yaml")
+                    .long("pipe-yaml")
+                    .help(wrap_help("Write the result into the stdin of the process instead of printing it to <stdout>."))
+                    .num_args(0),
+                Arg::new("stdin")
+                    .long("stdin")
+                    .value_name("FILE")
+                    .help(wrap_help("Connect <FILE> to the stdin of the process instead of inheriting the stdin of xyaml."))
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .conflicts_with("pipe-yaml")
+                    .num_args(1),
                 Arg::new("cmd")
                     .value_name("cmd")
                     .action(ArgAction::Append)
@@ -99,197 +881,1233 @@ fn config() -> Config {
                     .num_args(0..),
             ]),
         )
-        .get_matches();
+        .subcommand(
+            Command::new("merge")
+                .about("Merge the YAML files, the later files override the earlier ones")
+                .args([
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help(wrap_help("The files deep-merged in order as the multiple `--input` files, the transformations are applied to the result."))
+                        .num_args(1..),
+                    Arg::new("seq-merge")
+                        .long("seq-merge")
+                        .value_name("MODE")
+                        .help("How the sequences are merged, see `--merge-sequences`")
+                        .value_parser(merge::SequenceMerge::NAMES)
+                        .num_args(1),
+                    Arg::new("shallow")
+                        .long("shallow")
+                        .help(wrap_help("Replace the top-level entries of the earlier files with the ones of the later files instead of merging them recursively."))
+                        .conflicts_with("seq-merge")
+                        .num_args(0),
+                ]),
+        )
+        .subcommand(
+            Command::new("get")
+                .about("Print the value at the path of the resulting YAML")
+                .args([
+                    Arg::new("path")
+                        .value_name("PATH")
+                        .required(true)
+                        .help(wrap_help("The path of the value as with `--get`. Strings are printed as is, other values as YAML. Fails if the path does not exist unless `--default` is given."))
+                        .num_args(1),
+                    Arg::new("default")
+                        .long("default")
+                        .value_name("VALUE")
+                        .help("Print <VALUE> if the path does not exist")
+                        .num_args(1),
+                ]),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Validate the input documents without modifying them")
+                .args([
+                    Arg::new("input")
+                        .long("input")
+                        .value_name("FILE")
+                        .help("Read the YAML from the <FILE> instead of <stdin>, may be repeated")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .action(ArgAction::Append)
+                        .num_args(1),
+                    Arg::new("require-key")
+                        .long("require-key")
+                        .value_name("PATH")
+                        .help("Require the value at the path to be present and not `null`")
+                        .action(ArgAction::Append)
+                        .num_args(1),
+                    Arg::new("require-type")
+                        .long("require-type")
+                        .value_names(["PATH", "TYPE"])
+                        .help(wrap_help(&format!("Require the value at the path to be of the <TYPE>: {}.", Check::TYPES.map(|t| format!("`{t}`")).join(", "))))
+                        .action(ArgAction::Append)
+                        .num_args(2),
+                    Arg::new("require-value")
+                        .long("require-value")
+                        .value_names(["PATH", "VALUE"])
+                        .help("Require the value at the path to equal <VALUE> parsed as YAML")
+                        .action(ArgAction::Append)
+                        .num_args(2),
+                ]),
+        )
+        .subcommand(
+            Command::new("exec-all").args([
+                Arg::new("yaml-as-env")
+                    .long("yaml-as-env")
+                    .value_name("PREFIX")
+                    .help(wrap_help("Pass the scalar leaves of the nested mappings of the result to the processes as their only `<PREFIX>_KEY_SUBKEY` environment variables, see `--inherit-env`."))
+                    .num_args(1),
+                Arg::new("inherit-env")
+                    .long("inherit-env")
+                    .help(wrap_help("Pass the variables of `--yaml-as-env` in addition to the inherited environment instead of replacing it."))
+                    .requires("yaml-as-env")
+                    .num_args(0),
+                Arg::new("timeout")
+                    .long("timeout")
+                    .value_name("SECONDS")
+                    .help(wrap_help("Terminate a command and stop the chain if the command runs longer than <SECONDS>: send `SIGTERM` to it, then `SIGKILL` if it is still running 5 seconds later, and exit with the code 124. The limit applies to each command separately. With `--watch` the command is terminated and the chain is stopped without exiting."))
+                    .value_parser(clap::value_parser!(u64))
+                    .num_args(1),
+                Arg::new("commands")
+                    .value_name("FILE")
+                    .required(true)
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .help(wrap_help("A YAML or JSON sequence of the commands run one after another, e.g. `[{cmd: kubeval, args: [-], pipe-yaml: true}, {cmd: kubectl, args: [apply, -f, -], pipe-yaml: true}]`. The chain stops at the first command that fails and its exit code is returned."))
+                    .num_args(1),
+            ]),
+        )
+}
 
-    let env_values = matches.get_flag("env-values");
-    let mut replacements: Vec<_> = matches
-        .get_many::<String>("replacements")
+fn cli() -> Cli {
+    let args: Vec<std::ffi::OsString> = std::env::args_os().collect();
+    // The format is looked up before the parsing to apply to its errors.
+    let json_errors = args
+        .windows(2)
+        .any(|w| w[0] == "--error-format" && w[1] == "json")
+        || args.iter().any(|a| a == "--error-format=json");
+    JSON_ERRORS.store(json_errors, Ordering::Relaxed);
+    let mut matches = get_matches(&args);
+    let profiles: Vec<String> = matches
+        .get_many::<String>("profile")
         .unwrap_or_default()
-        .collect::<Vec<_>>()
-        .chunks(2)
-        .map(|chunk| (chunk[0].clone(), chunk[1].clone()))
+        .cloned()
         .collect();
-    if env_values {
-        for entry in replacements.iter_mut() {
-            entry.1 = std::env::var(&entry.1).unwrap_or_else(|e| {
-                fail!(
-                    "Failed to read the referred env variable `{}`\nerror=`{e}`",
-                    entry.1
-                )
-            });
+    if !profiles.is_empty() {
+        let mut expanded = vec![args[0].clone()];
+        for name in profiles.iter() {
+            expanded.extend(profile::profile_args(name).into_iter().map(Into::into));
+        }
+        expanded.extend(args.into_iter().skip(1));
+        matches = get_matches(&expanded);
+    }
+    let error_format = matches.get_one::<String>("error-format").unwrap();
+    JSON_ERRORS.store(error_format == "json", Ordering::Relaxed);
+
+    let override_env = matches.get_flag("env-file-override");
+    for file in matches.get_many::<PathBuf>("env-file").unwrap_or_default() {
+        let vars = xyaml::dotenv::parse_file(file).unwrap_or_else(|e| exit_with(e));
+        for (name, value) in vars {
+            if override_env || std::env::var_os(&name).is_none() {
+                std::env::set_var(name, value);
+            }
         }
     }
 
-    let mut config = Config {
+    let env_values = matches.get_flag("env-values");
+    let mut operations: Vec<(usize, Operation)> = vec![];
+    let typed_sets = [
+        ("replacements", ValueHint::Yaml),
+        ("set-string", ValueHint::String),
+        ("set-int", ValueHint::Int),
+        ("set-float", ValueHint::Float),
+        ("set-bool", ValueHint::Bool),
+    ];
+    for (id, hint) in typed_sets {
+        for (idx, mut values) in occurrences(&matches, id) {
+            let value = values.pop().unwrap();
+            let path = values.pop().unwrap();
+            let value = if env_values {
+                std::env::var(&value).unwrap_or_else(|e| {
+                    fail!(EnvVar: "Failed to read the referred env variable `{value}`\nerror=`{e}`")
+                })
+            } else {
+                value
+            };
+            operations.push((idx, Operation::Set { path, value, hint }));
+        }
+    }
+    for (idx, mut values) in occurrences(&matches, "set-null") {
+        let path = values.remove(0);
+        let value = "null".to_string();
+        let hint = ValueHint::Null;
+        operations.push((idx, Operation::Set { path, value, hint }));
+    }
+    for (idx, values) in occurrences(&matches, "set-default") {
+        let [path, value] = <[String; 2]>::try_from(values).unwrap();
+        operations.push((idx, Operation::SetDefault { path, value }));
+    }
+    // The values files precede all command line operations, so that the
+    // latter override them.
+    for (_, mut values) in occurrences(&matches, "values-file") {
+        let file = PathBuf::from(values.remove(0));
+        let content = std::fs::read_to_string(&file).unwrap_or_else(
+            |e| fail!(Io: "Failed to read the values file `{file:?}`\nerror=`{e}`"),
+        );
+        let values: Value = serde_yaml::from_str(&content).unwrap_or_else(
+            |e| fail!(Parse: "Failed to parse the values file `{file:?}` as YAML: {e}"),
+        );
+        if !values.is_mapping() {
+            fail!(TypeMismatch: "The values file `{file:?}` is not a YAML mapping");
+        }
+        let paths = xyaml::flatten_to_paths(&values, vec![]).unwrap_or_else(|e| exit_with(e));
+        for (path, value) in paths {
+            let hint = ValueHint::Yaml;
+            operations.push((0, Operation::Set { path, value, hint }));
+        }
+    }
+    for (idx, values) in occurrences(&matches, "set-file") {
+        let file = PathBuf::from(&values[0]);
+        let content = std::fs::read_to_string(&file)
+            .unwrap_or_else(|e| fail!(Io: "Failed to read the set file `{file:?}`\nerror=`{e}`"));
+        let pairs = parse_set_pairs(&content)
+            .unwrap_or_else(|e| fail!(Parse: "Failed to parse the set file `{file:?}`: {e}"));
+        for (path, value) in pairs {
+            let hint = ValueHint::Yaml;
+            operations.push((idx, Operation::Set { path, value, hint }));
+        }
+    }
+    if matches.get_flag("set-from-stdin") {
+        let idx = matches.index_of("set-from-stdin").unwrap();
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .unwrap_or_else(|e| fail!(Io: "Failed to read the values from stdin\nerror=`{e}`"));
+        let pairs = parse_set_pairs(&content)
+            .unwrap_or_else(|e| fail!(Parse: "Failed to parse the values from stdin: {e}"));
+        for (path, value) in pairs {
+            let hint = ValueHint::Yaml;
+            operations.push((idx, Operation::Set { path, value, hint }));
+        }
+    }
+    for (idx, values) in occurrences(&matches, "set-join") {
+        let mut values = values.into_iter();
+        let path = values.next().unwrap();
+        let separator = values.next().unwrap();
+        let values = values.collect();
+        operations.push((
+            idx,
+            Operation::SetJoin {
+                path,
+                separator,
+                values,
+            },
+        ));
+    }
+    for (idx, values) in occurrences(&matches, "set-from-checksum") {
+        let [path, algorithm, file] = <[String; 3]>::try_from(values).unwrap();
+        let algorithm = checksum::Algorithm::from_name(&algorithm).unwrap_or_else(|| {
+            fail!(
+                InvalidArgs: "Unsupported checksum algorithm `{algorithm}`, expected one of: sha256, sha1, md5"
+            )
+        });
+        operations.push((
+            idx,
+            Operation::SetFromChecksum {
+                path,
+                algorithm,
+                file: PathBuf::from(file),
+            },
+        ));
+    }
+    for (idx, values) in occurrences(&matches, "set-if-regex") {
+        let [path, pattern, value] = <[String; 3]>::try_from(values).unwrap();
+        let pattern = regex::Regex::new(&pattern)
+            .unwrap_or_else(|e| fail!(InvalidArgs: "Invalid `--set-if-regex` pattern: {e}"));
+        operations.push((
+            idx,
+            Operation::SetIfRegex {
+                path,
+                pattern,
+                value,
+            },
+        ));
+    }
+    for (idx, values) in occurrences(&matches, "replace-in-keys") {
+        let [path, old, new] = <[String; 3]>::try_from(values).unwrap();
+        operations.push((idx, Operation::ReplaceInKeys { path, old, new }));
+    }
+    for (idx, values) in occurrences(&matches, "set-from-env-file") {
+        let [file, path] = <[String; 2]>::try_from(values).unwrap();
+        let file = PathBuf::from(file);
+        operations.push((idx, Operation::SetFromEnvFile { file, path }));
+    }
+    for (idx, values) in occurrences(&matches, "set-integer-range") {
+        let mut values = values.into_iter();
+        let path = values.next().unwrap();
+        let mut numbers = values.map(|v| {
+            v.parse::<i64>().unwrap_or_else(
+                |e| fail!(InvalidArgs: "Invalid `--set-integer-range` number `{v}`: {e}"),
+            )
+        });
+        let start = numbers.next().unwrap();
+        let end = numbers.next().unwrap();
+        let step = numbers.next().unwrap_or(1);
+        if step == 0 {
+            fail!(InvalidArgs: "The `--set-integer-range` step must not be 0\n  path=`{path}`");
+        }
+        operations.push((
+            idx,
+            Operation::SetIntegerRange {
+                path,
+                start,
+                end,
+                step,
+            },
+        ));
+    }
+    for (idx, values) in occurrences(&matches, "unset-if-default") {
+        let [path, default] = <[String; 2]>::try_from(values).unwrap();
+        operations.push((idx, Operation::UnsetIfDefault { path, default }));
+    }
+    for (idx, values) in occurrences(&matches, "inject-env") {
+        let [path, names_path] = <[String; 2]>::try_from(values).unwrap();
+        operations.push((idx, Operation::InjectEnv { path, names_path }));
+    }
+    for (idx, values) in occurrences(&matches, "set-from-file-lines") {
+        let [path, file] = <[String; 2]>::try_from(values).unwrap();
+        let file = PathBuf::from(file);
+        let skip_empty = matches.get_flag("skip-empty-lines");
+        operations.push((
+            idx,
+            Operation::SetFromFileLines {
+                path,
+                file,
+                skip_empty,
+            },
+        ));
+    }
+    for (id, parse) in [("set-from-file", false), ("set-from-yaml-file", true)] {
+        for (idx, values) in occurrences(&matches, id) {
+            let [path, file] = <[String; 2]>::try_from(values).unwrap();
+            let file = PathBuf::from(file);
+            operations.push((idx, Operation::SetFromFile { path, file, parse }));
+        }
+    }
+    for (idx, values) in occurrences(&matches, "set-from-query") {
+        let [path, file, query] = <[String; 3]>::try_from(values).unwrap();
+        let file = PathBuf::from(file);
+        operations.push((idx, Operation::SetFromQuery { path, file, query }));
+    }
+    for (idx, mut values) in occurrences(&matches, "force-string") {
+        operations.push((idx, Operation::ForceString(values.remove(0))));
+    }
+    for (idx, mut values) in occurrences(&matches, "delete") {
+        operations.push((idx, Operation::Delete(values.remove(0))));
+    }
+    for (idx, values) in occurrences(&matches, "append") {
+        let [path, value] = <[String; 2]>::try_from(values).unwrap();
+        operations.push((idx, Operation::Append { path, value }));
+    }
+    for (idx, values) in occurrences(&matches, "copy") {
+        let [src, dst] = <[String; 2]>::try_from(values).unwrap();
+        operations.push((idx, Operation::Copy { src, dst }));
+    }
+    for (idx, values) in occurrences(&matches, "replace-pattern") {
+        let [pattern, replacement] = <[String; 2]>::try_from(values).unwrap();
+        let pattern = regex::Regex::new(&pattern)
+            .unwrap_or_else(|e| fail!(InvalidArgs: "Invalid `--replace-pattern` pattern: {e}"));
+        operations.push((
+            idx,
+            Operation::ReplacePattern {
+                pattern,
+                replacement,
+            },
+        ));
+    }
+    if let Some(counts) = matches.get_many::<usize>("repeat-last") {
+        let indices = matches.indices_of("repeat-last").unwrap();
+        for (idx, n) in indices.zip(counts) {
+            operations.push((idx, Operation::RepeatLast(*n)));
+        }
+    }
+    operations.sort_by_key(|(idx, _)| *idx);
+    let operations = operations.into_iter().map(|(_, op)| op).collect();
+
+    xyaml::set_quiet(matches.get_flag("quiet"));
+    let config = Config {
         require_null: matches.get_flag("require-null"),
-        replacements,
+        delete_missing_ok: matches.get_flag("delete-missing-ok"),
+        create: matches.get_flag("create"),
+        operations,
+        repeat_count: *matches.get_one::<usize>("repeat-count").unwrap(),
         env_substitutions: matches
             .get_many::<String>("env-substitutions")
             .unwrap_or_default()
-            .map(Clone::clone)
+            .map(|v| match v.split_once('=') {
+                Some((name, default)) => (name.to_string(), Some(default.to_string())),
+                None => (v.clone(), None),
+            })
             .collect(),
-        output: matches.get_one::<PathBuf>("output").map(Clone::clone),
-        input: matches.get_one::<PathBuf>("input").map(Clone::clone),
-        exec: None,
-        subst_args_from_env: false,
-        exec_args: vec![],
+        env_subst_prefixes: matches
+            .get_many::<String>("env-subst-prefix")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        env_subst_prefix_strip: matches.get_flag("env-subst-prefix-strip"),
+        typed_env_subst: matches.get_flag("typed-env-subst"),
+        env_subst_all: matches.get_flag("env-subst-all"),
+        env_subst_undefined: UndefinedVars::from_name(
+            matches.get_one::<String>("env-subst-undefined").unwrap(),
+        ),
+        placeholder_start: matches
+            .get_one::<String>("placeholder-start")
+            .unwrap()
+            .clone(),
+        placeholder_end: matches
+            .get_one::<String>("placeholder-end")
+            .unwrap()
+            .clone(),
+        validate_env_subst_coverage: matches.get_flag("validate-env-subst-coverage"),
+        strict: matches.get_flag("strict"),
+        verbose: matches.get_flag("verbose"),
+        template: matches.get_one::<PathBuf>("eval-template").cloned(),
+        get: matches.get_one::<String>("get").cloned(),
+        get_default: None,
+        document: matches.get_one::<usize>("document").copied(),
+        document_selector: matches.get_one::<String>("select-document").map(|s| {
+            let Some((path, value)) = s.split_once('=') else {
+                fail!(InvalidArgs: "Invalid `--select-document` `{s}`, expected `PATH=VALUE`")
+            };
+            let value = serde_yaml::from_str(value).unwrap_or_else(|e| {
+                fail!(Parse: "The `--select-document` value is not a valid YAML:\n  value=`{value}`\n  error=`{e}`")
+            });
+            (path.to_string(), value)
+        }),
+        require_keys: matches
+            .get_many::<String>("require-key")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        require_non_null: matches
+            .get_many::<String>("require-all-non-null")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        merge_from_exec: matches
+            .get_many::<String>("merge-from-exec")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        merge_files: matches
+            .get_many::<String>("merge")
+            .unwrap_or_default()
+            .map(PathBuf::from)
+            .collect(),
+        merge_sequences: match matches.get_flag("merge-sequences-append") {
+            true => merge::SequenceMerge::Append,
+            false => matches
+                .get_one::<String>("merge-sequences")
+                .map_or_else(Default::default, |name| {
+                    merge::SequenceMerge::from_name(name)
+                }),
+        },
+        merge_strategies: matches
+            .get_many::<String>("merge-strategy")
+            .unwrap_or_default()
+            .map(|s| {
+                let (path, name) = s.rsplit_once(':').unwrap_or_else(|| {
+                    fail!(InvalidArgs: "Invalid `--merge-strategy` `{s}`, expected `PATH:MODE`")
+                });
+                if !merge::SequenceMerge::NAMES.contains(&name) {
+                    fail!(
+                        InvalidArgs: "Invalid `--merge-strategy` mode `{name}`, expected one of {:?}",
+                        merge::SequenceMerge::NAMES
+                    );
+                }
+                (path.to_string(), merge::SequenceMerge::from_name(name))
+            })
+            .collect(),
+        no_yaml_tags: matches.get_flag("no-yaml-tags"),
+        copy_subtrees: occurrences(&matches, "copy-subtree")
+            .into_iter()
+            .map(|(_, values)| {
+                let [src, file, dst] = <[String; 3]>::try_from(values).unwrap();
+                (src, PathBuf::from(file), dst)
+            })
+            .collect(),
+        trim_strings: matches.get_flag("trim-strings"),
+        trim_strings_at: matches
+            .get_many::<String>("trim-string-values-at")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        trim_keys: matches.get_flag("trim-keys"),
+        sort_keys: matches.get_flag("sort-keys"),
+        sort_keys_at: matches
+            .get_many::<String>("sort-keys-at")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        input_format: InputFormat::from_name(matches.get_one::<String>("input-format").unwrap()),
+        output_format: OutputFormat::from_name(matches.get_one::<String>("output-format").unwrap()),
+        path_comments: matches.get_flag("path-comments"),
+        flow_style: matches.get_flag("flow-style"),
+        null_style: NullStyle::from_name(matches.get_one::<String>("null-style").unwrap()),
+        configmap: output::ConfigMapOptions {
+            name: matches.get_one::<String>("configmap-name").cloned(),
+            namespace: matches.get_one::<String>("configmap-namespace").cloned(),
+            key: matches.get_one::<String>("configmap-key").cloned(),
+        },
+        ssm: output::SsmOptions {
+            prefix: matches.get_one::<String>("ssm-prefix").unwrap().clone(),
+            secure_paths: matches
+                .get_many::<String>("ssm-secure-paths")
+                .unwrap_or_default()
+                .cloned()
+                .collect(),
+        },
+    };
+    let mut cli = Cli {
+        config,
+        output: matches.get_one::<PathBuf>("output").cloned(),
+        output_encoding: OutputEncoding::from_name(
+            matches.get_one::<String>("output-encoding").unwrap(),
+        ),
+        output_mode: matches
+            .get_one::<String>("output-file-permissions")
+            .map(|mode| {
+                u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .unwrap_or_else(|| {
+                        fail!(InvalidArgs: "Invalid `--output-file-permissions` mode `{mode}`, expected an octal number such as `0600`")
+                    })
+            }),
+        inputs: matches
+            .get_many::<PathBuf>("input")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        in_place: matches.get_flag("in-place"),
+        shallow_merge: false,
+        dry_run: matches.get_flag("dry-run"),
+        fail_if_unchanged: matches.get_flag("fail-if-unchanged"),
+        preserve_comments: matches.get_flag("preserve-comments"),
+        schema: matches
+            .get_one::<PathBuf>("validate-schema")
+            .map(|path| xyaml::schema::load_schema(path).unwrap_or_else(|e| exit_with(e))),
+        checks: None,
+        watch: matches.get_flag("watch"),
+        watch_debounce: Duration::from_millis(*matches.get_one::<u64>("debounce-ms").unwrap()),
+        expand_anchors: matches.get_flag("expand-anchors"),
+        exec: vec![],
+        exec_yaml_env_prefix: None,
+        exec_yaml_env_override: true,
+        exec_env_clear: false,
+        exec_timeout: None,
+        exec_pid_file: matches.get_one::<PathBuf>("exec-pid-file").cloned(),
+        exec_log_command: matches.get_flag("exec-log-command"),
+        exec_cwd_from_yaml: matches.get_one::<String>("exec-cwd-from-yaml").cloned(),
+        exec_on_success: exec_hook(&matches, "exec-on-success"),
+        exec_on_failure: exec_hook(&matches, "exec-on-failure"),
     };
+    #[cfg(not(unix))]
+    if cli.output_mode.is_some() {
+        warn!("Warning: The output file permissions cannot be set on this platform");
+    }
+    if cli.in_place && cli.inputs.len() > 1 {
+        fail!(InvalidArgs: "`--in-place` cannot be used with multiple `--input` files");
+    }
     if let Some(matches) = matches.subcommand_matches("exec") {
         let cmd: Vec<_> = matches
             .get_many::<String>("cmd")
             .unwrap()
             .map(Clone::clone)
             .collect();
-        config.exec = Some(PathBuf::from(&cmd[0]));
-        config.subst_args_from_env = matches.get_flag("subst-args-with-env");
-        let mut exec_args: Vec<String> = cmd.into_iter().skip(1).collect();
-        if config.subst_args_from_env {
-            exec_args = substitute_exec_args(&exec_args);
+        let pipe_yaml = matches.get_flag("pipe-yaml");
+        cli.exec_yaml_env_prefix = matches.get_one::<String>("yaml-as-env").cloned();
+        cli.exec_env_clear = cli.exec_yaml_env_prefix.is_some() && !matches.get_flag("inherit-env");
+        if matches.get_flag("env-from-yaml") {
+            cli.exec_yaml_env_prefix = Some(String::new());
+            cli.exec_yaml_env_override = matches.get_flag("env-from-yaml-override");
+        }
+        cli.exec_timeout = matches
+            .get_one::<u64>("timeout")
+            .map(|s| Duration::from_secs(*s));
+        if pipe_yaml && (cli.output.is_some() || cli.in_place) {
+            fail!(InvalidArgs: "`exec --pipe-yaml` cannot be used with `--output` or `--in-place`");
+        }
+        let mut exec_args: Vec<String> = cmd[1..].to_vec();
+        if matches.get_flag("subst-args-with-env") {
+            let config = &cli.config;
+            exec_args = substitute_exec_args(
+                &exec_args,
+                &config.placeholder_start,
+                &config.placeholder_end,
+            );
+        }
+        cli.exec = vec![ExecCommand {
+            path: PathBuf::from(&cmd[0]),
+            args: exec_args,
+            pipe_yaml,
+            stdin: matches.get_one::<PathBuf>("stdin").cloned(),
+        }];
+    }
+    if let Some(matches) = matches.subcommand_matches("merge") {
+        if !cli.inputs.is_empty() || cli.in_place {
+            fail!(InvalidArgs: "`merge` cannot be used with `--input` or `--in-place`");
+        }
+        cli.inputs = matches
+            .get_many::<PathBuf>("files")
+            .unwrap()
+            .cloned()
+            .collect();
+        if let Some(name) = matches.get_one::<String>("seq-merge") {
+            cli.config.merge_sequences = merge::SequenceMerge::from_name(name);
+        }
+        cli.shallow_merge = matches.get_flag("shallow");
+    }
+    if let Some(matches) = matches.subcommand_matches("get") {
+        if cli.config.get.is_some() || cli.config.template.is_some() || cli.preserve_comments {
+            fail!(InvalidArgs: "`get` cannot be used with `--get`, `--eval-template` or `--preserve-comments`");
+        }
+        cli.config.get = matches.get_one::<String>("path").cloned();
+        cli.config.get_default = matches.get_one::<String>("default").cloned();
+    }
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let mut checks: Vec<(usize, Check)> = vec![];
+        for (idx, mut values) in occurrences(matches, "require-key") {
+            checks.push((idx, Check::Exists(values.remove(0))));
+        }
+        for (idx, values) in occurrences(matches, "require-type") {
+            let [path, type_name] = <[String; 2]>::try_from(values).unwrap();
+            if !Check::TYPES.contains(&type_name.as_str()) {
+                fail!(
+                    InvalidArgs: "Invalid `--require-type` type `{type_name}`, expected one of {:?}",
+                    Check::TYPES
+                );
+            }
+            checks.push((idx, Check::Type { path, type_name }));
+        }
+        for (idx, values) in occurrences(matches, "require-value") {
+            let [path, value] = <[String; 2]>::try_from(values).unwrap();
+            let value = serde_yaml::from_str(&value).unwrap_or_else(|e| {
+                fail!(Parse: "The `--require-value` value is not a valid YAML:\n  value=`{value}`\n  error=`{e}`")
+            });
+            checks.push((idx, Check::Equals { path, value }));
+        }
+        checks.sort_by_key(|(idx, _)| *idx);
+        cli.checks = Some(checks.into_iter().map(|(_, check)| check).collect());
+        cli.inputs = matches
+            .get_many::<PathBuf>("input")
+            .unwrap_or_default()
+            .cloned()
+            .collect();
+    }
+    if let Some(matches) = matches.subcommand_matches("exec-all") {
+        let file = matches.get_one::<PathBuf>("commands").unwrap();
+        cli.exec = exec_all::load_commands(file);
+        cli.exec_yaml_env_prefix = matches.get_one::<String>("yaml-as-env").cloned();
+        cli.exec_env_clear = cli.exec_yaml_env_prefix.is_some() && !matches.get_flag("inherit-env");
+        cli.exec_timeout = matches
+            .get_one::<u64>("timeout")
+            .map(|s| Duration::from_secs(*s));
+        if cli.exec.iter().any(|c| c.pipe_yaml) && (cli.output.is_some() || cli.in_place) {
+            fail!(InvalidArgs: "`exec-all` commands with `pipe-yaml` cannot be used with `--output` or `--in-place`");
+        }
+    }
+    // The formats not given explicitly follow the file extensions.
+    let is_default = |id| matches.value_source(id) == Some(ValueSource::DefaultValue);
+    if is_default("input-format") {
+        if let Some(format) = cli
+            .inputs
+            .first()
+            .and_then(|p| InputFormat::from_extension(p))
+        {
+            cli.config.input_format = format;
+        }
+    }
+    let output_path = match cli.in_place {
+        true => cli.inputs.first(),
+        false => cli.output.as_ref(),
+    };
+    if is_default("output-format") {
+        if let Some(format) = output_path.and_then(|p| OutputFormat::from_extension(p)) {
+            cli.config.output_format = format;
+        }
+    }
+    if cli.preserve_comments && cli.config.output_format != OutputFormat::Yaml {
+        fail!(InvalidArgs: "`--preserve-comments` can only be used with the YAML output format");
+    }
+    cli
+}
+
+fn exec_cwd(yaml: &Value, path: &str) -> PathBuf {
+    let value = xyaml::find_value(yaml, path)
+        .unwrap_or_else(|e| exit_with(e))
+        .unwrap_or_else(|| fail!("No exec working directory at the path `{path}`"));
+    let dir = value.as_str().unwrap_or_else(|| {
+        fail!("The exec working directory is not a string:\n  obj={value:?}\n  path=`{path}`")
+    });
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        fail!("The exec working directory `{dir:?}` does not exist\n  path=`{path}`");
+    }
+    dir
+}
+
+/// Formats the command line quoting the words the shell would split or
+/// interpret.
+fn command_line(path: &Path, args: &[String]) -> String {
+    std::iter::once(path.to_string_lossy().as_ref())
+        .chain(args.iter().map(String::as_str))
+        .map(|word| {
+            let plain = !word.is_empty()
+                && word
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_./=:,@%+".contains(c));
+            match plain {
+                true => word.to_string(),
+                false => output::shell_quote(word),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn exec_hook(matches: &clap::ArgMatches, id: &str) -> Option<(PathBuf, Vec<String>)> {
+    let cmd = matches.get_one::<PathBuf>(id)?.clone();
+    let args = matches
+        .get_many::<String>(&format!("{id}-arg"))
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    Some((cmd, args))
+}
+
+/// Returns the values of every occurrence of the argument together with the
+/// command line index of the occurrence, so that different arguments can be
+/// applied in the order they were given.
+fn occurrences(matches: &clap::ArgMatches, id: &str) -> Vec<(usize, Vec<String>)> {
+    let mut indices = matches.indices_of(id).unwrap_or_default();
+    matches
+        .get_occurrences::<String>(id)
+        .unwrap_or_default()
+        .map(|values| {
+            let values: Vec<String> = values.cloned().collect();
+            let idx = indices.next().unwrap_or_default();
+            indices.by_ref().take(values.len() - 1).for_each(drop);
+            (idx, values)
+        })
+        .collect()
+}
+
+/// Parses the alternating path and value lines of `--set-file`. The block
+/// scalar values continue on the following indented lines and the quoted ones
+/// until the closing quote.
+fn parse_set_pairs(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = vec![];
+    let mut lines = content.lines().enumerate().peekable();
+    while let Some((line_no, path)) = lines.next() {
+        if path.trim().is_empty() || path.trim_start().starts_with('#') {
+            continue;
+        }
+        let Some((_, first)) = lines.next() else {
+            return Err(format!("line {}: no value for `{path}`", line_no + 1));
+        };
+        let mut value = first.to_string();
+        let trimmed = first.trim_start();
+        if trimmed.starts_with(['|', '>']) {
+            while let Some((_, line)) =
+                lines.next_if(|(_, l)| l.trim().is_empty() || l.starts_with([' ', '\t']))
+            {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if trimmed.starts_with(['"', '\'']) {
+            while serde_yaml::from_str::<Value>(&value).is_err() {
+                let Some((_, line)) = lines.next() else {
+                    return Err(format!(
+                        "line {}: unterminated quoted value of `{path}`",
+                        line_no + 2
+                    ));
+                };
+                value.push('\n');
+                value.push_str(line);
+            }
         }
-        config.exec_args = exec_args;
+        pairs.push((path.trim().to_string(), value));
     }
-    config
+    Ok(pairs)
 }
 
-fn substitute_exec_args(args: &[String]) -> Vec<String> {
+/// Replaces every `{{VAR}}` placeholder, or the one with the custom
+/// delimiters, within the arguments with the value of the environment
+/// variable, e.g. `--url=http://{{HOST}}/api`. The arguments are never split,
+/// even if the values have spaces.
+fn substitute_exec_args(args: &[String], start: &str, end: &str) -> Vec<String> {
     let mut result = vec![];
     for a in args.iter() {
-        let val = if a.starts_with("{{") && a.ends_with("}}") {
-            let var = a[2..a.len() - 2].to_string();
-            std::env::var(&var)
-                .unwrap_or_else(|e| {
-                    fail!(
-                        "exec: Failed to read the referred env variable `{}`\nerror=`{e}`",
-                        var
-                    )
-                })
-                .to_string()
-        } else {
-            a.clone()
-        };
+        let mut val = String::new();
+        let mut rest = a.as_str();
+        while let Some(pos) = rest.find(start) {
+            let var_start = pos + start.len();
+            let Some(len) = rest[var_start..].find(end) else {
+                break;
+            };
+            let var = &rest[var_start..var_start + len];
+            let value = std::env::var(var).unwrap_or_else(|e| {
+                fail!(EnvVar: "exec: Failed to read the referred env variable `{var}`\nerror=`{e}`")
+            });
+            val.push_str(&rest[..pos]);
+            val.push_str(&value);
+            rest = &rest[var_start + len + end.len()..];
+        }
+        val.push_str(rest);
         result.push(val);
     }
     result
 }
 
 fn main() {
-    let config = config();
+    let cli = cli();
+    if let Some(checks) = &cli.checks {
+        check(&cli, checks);
+    }
+    if cli.watch {
+        watch(&cli);
+    }
 
-    let yaml_string = if let Some(path) = config.input {
-        let mut file = File::open(path.clone())
-            .unwrap_or_else(|e| fail!("Failed to open the intput file `{path:?}`\nerror=`{e}`"));
-        let mut yaml_string = String::new();
-        file.read_to_string(&mut yaml_string)
-            .unwrap_or_else(|e| fail!("Failed to read the intput file `{path:?}`\nerror=`{e}`"));
-        yaml_string
-    } else {
+    let yaml_strings = if cli.inputs.is_empty() {
         let mut yaml_string = String::new();
         io::stdin()
             .read_to_string(&mut yaml_string)
             .expect("Failed to read from stdin");
-        yaml_string
+        vec![yaml_string]
+    } else {
+        read_inputs(&cli.inputs).unwrap_or_else(|e| exit_with(e))
     };
+    let t = transform_input(&cli, &yaml_strings).unwrap_or_else(|e| exit_with(e));
 
-    let mut yaml: Value =
-        serde_yaml::from_str(&yaml_string).unwrap_or_else(|e| fail!("Failed to parse YAML: {e}"));
-
-    for (path, value) in config.replacements.iter() {
-        update_value(&mut yaml, path, value, config.require_null);
+    if cli.dry_run {
+        let name = |path: Option<&PathBuf>, default: &str| {
+            path.map_or(default.to_string(), |p| p.display().to_string())
+        };
+        // The diff is against the first input the others are merged into.
+        let old_name = name(cli.inputs.first(), "<stdin>");
+        let new_name = match cli.in_place {
+            true => old_name.clone(),
+            false => name(cli.output.as_ref(), "<stdout>"),
+        };
+        let diff = xyaml::diff::unified_diff(&yaml_strings[0], &t.yaml, &old_name, &new_name);
+        if diff.is_empty() {
+            eprintln!("No changes");
+        } else {
+            eprint!("{diff}");
+        }
+        if cli.fail_if_unchanged && !t.changed {
+            std::process::exit(UNCHANGED_EXIT_CODE);
+        }
+        return;
+    }
+    if cli.fail_if_unchanged && !t.changed {
+        eprintln!("No changes");
+        std::process::exit(UNCHANGED_EXIT_CODE);
     }
 
-    substitute_env(&mut yaml, &config.env_substitutions);
+    write_output(&cli, &t.yaml);
 
-    let modified_yaml = serde_yaml::to_string(&yaml).expect("Failed to serialize YAML");
-    if let Some(path) = config.output {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path.clone())
-            .unwrap_or_else(|e| fail!("Failed to open the output file: {e}"));
-        file.write_all(modified_yaml.as_bytes())
-            .unwrap_or_else(|e| fail!("Failed to write the output file: {e}"));
+    if !cli.exec.is_empty() {
+        let mut status = Ok(0);
+        for command in &cli.exec {
+            let mut handle = spawn_exec(&cli, command, &t.documents[t.exec_doc], &t.yaml);
+            status = wait_exec(&mut handle, command, cli.exec_timeout)
+                .map(|status| status.map_or(TIMEOUT_EXIT_CODE, exit_code))
+                .map_err(|e| (command, e));
+            if !matches!(status, Ok(0)) {
+                break;
+            }
+        }
+        let succeeded = matches!(status, Ok(0));
+        let hook = if succeeded {
+            &cli.exec_on_success
+        } else {
+            &cli.exec_on_failure
+        };
+        if let Some((path, args)) = hook {
+            let mut cmd = std::process::Command::new(path);
+            let cmd = cmd.args(args);
+            cmd.status().unwrap_or_else(|e| {
+                fail!("Failed to run the exec hook:\ncmd=`{cmd:?}`\nerror=`{e}`")
+            });
+        }
+        let code = match status {
+            Ok(code) => code,
+            Err((command, e)) => fail!(
+                "Failed to wait for the process:\ncmd=`{}`\nerror=`{e}`",
+                command_line(&command.path, &command.args)
+            ),
+        };
+        if code != 0 {
+            std::process::exit(code);
+        }
+    }
+}
+
+/// Runs the checks against every document of the inputs, exits reporting all
+/// of the failures if any.
+fn check(cli: &Cli, checks: &[Check]) -> ! {
+    let inputs: Vec<(String, String)> = if cli.inputs.is_empty() {
+        let mut yaml_string = String::new();
+        io::stdin()
+            .read_to_string(&mut yaml_string)
+            .expect("Failed to read from stdin");
+        vec![("<stdin>".to_string(), yaml_string)]
     } else {
-        println!("{}", modified_yaml);
+        let yaml_strings = read_inputs(&cli.inputs).unwrap_or_else(|e| exit_with(e));
+        let names = cli.inputs.iter().map(|p| p.display().to_string());
+        names.zip(yaml_strings).collect()
+    };
+    let mut failures = vec![];
+    for (name, yaml_string) in inputs.iter() {
+        let file_prefix = match inputs.len() {
+            1 => String::new(),
+            _ => format!("{name}: "),
+        };
+        let documents = match xyaml::load(yaml_string, &cli.config) {
+            Ok(documents) => documents,
+            Err(e) => {
+                failures.push(format!("  {file_prefix}{e}"));
+                continue;
+            }
+        };
+        for (i, document) in documents.iter().enumerate() {
+            let prefix = match documents.len() {
+                1 => file_prefix.clone(),
+                _ => format!("{file_prefix}document {i}: "),
+            };
+            for error in xyaml::check::validate(document, checks) {
+                failures.push(format!("  {prefix}{error}"));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        fail!("Check failed:\n{}", failures.join("\n"));
     }
+    std::process::exit(0);
+}
 
-    if let Some(path) = config.exec {
-        let mut cmd = std::process::Command::new(path);
-        let cmd = cmd.args(&config.exec_args);
-        let mut handle = cmd
-            .spawn()
-            .unwrap_or_else(|e| fail!("Failed to spawn the process:\ncmd=`{cmd:?}`\nerror=`{e}`"));
-        handle.wait().ok();
+fn read_inputs(paths: &[PathBuf]) -> xyaml::Result<Vec<String>> {
+    let io_error = |message: String| XyamlError::new(message).with_kind(xyaml::ErrorKind::Io);
+    let mut yaml_strings = vec![];
+    for path in paths {
+        let mut file = File::open(path).map_err(|e| {
+            io_error(format!(
+                "Failed to open the intput file `{path:?}`\nerror=`{e}`"
+            ))
+        })?;
+        let mut yaml_string = String::new();
+        file.read_to_string(&mut yaml_string).map_err(|e| {
+            io_error(format!(
+                "Failed to read the intput file `{path:?}`\nerror=`{e}`"
+            ))
+        })?;
+        yaml_strings.push(yaml_string);
     }
+    Ok(yaml_strings)
 }
 
-fn substitute_env(obj: &mut Value, vars: &[String]) {
-    let vars: HashMap<String, String> = vars
-        .iter()
-        .map(|v| (format!("{{{{{}}}}}", v), v.clone()))
-        .collect();
-    do_substitute_env(obj, &vars)
+struct Transformed {
+    documents: Vec<Value>,
+    /// The index of the document the exec working directory and environment
+    /// are taken from.
+    exec_doc: usize,
+    /// The rendered result.
+    yaml: String,
+    /// Whether the transformations changed any of the input documents.
+    changed: bool,
 }
 
-fn do_substitute_env(obj: &mut Value, vars: &HashMap<String, String>) {
-    if let Some(map) = obj.as_mapping_mut() {
-        for (_, obj) in map.iter_mut() {
-            do_substitute_env(obj, vars);
+/// Deep-merges the inputs and transforms the documents.
+fn transform_input(cli: &Cli, yaml_strings: &[String]) -> xyaml::Result<Transformed> {
+    let config = &cli.config;
+    if !cli.expand_anchors && yaml_strings.iter().any(|y| xyaml::has_yaml_anchors(y)) {
+        warn!("Warning: The input YAML anchors and aliases are expanded in the output, pass `--expand-anchors` to silence this warning");
+    }
+    let mut documents = xyaml::load(&yaml_strings[0], config)?;
+    for yaml_string in yaml_strings[1..].iter() {
+        let overlay = xyaml::load(yaml_string, config)?;
+        if documents.len() != 1 || overlay.len() != 1 {
+            return Err(xyaml::XyamlError::new(
+                "Multi-document inputs cannot be merged with other inputs",
+            ));
         }
-    } else if let Some(seq) = obj.as_sequence_mut() {
-        for obj in seq.iter_mut() {
-            do_substitute_env(obj, vars);
+        if cli.shallow_merge {
+            merge::shallow_merge(&mut documents[0], &overlay[0]);
+        } else {
+            merge::deep_merge_with(
+                &mut documents[0],
+                &overlay[0],
+                config.merge_sequences,
+                &config.merge_strategies,
+            )?;
         }
-    } else if let Some(s) = obj.as_str() {
-        if let Some(var) = vars.get(s) {
-            let new_value = std::env::var(var).unwrap_or_else(|e| {
-                fail!("Failed to read the referred env variable `{var}`\nerror=`{e}`")
-            });
-            *obj = serde_yaml::from_str(&new_value).unwrap_or_else(|e| {
-                fail!("New value is not a valid YAML:\n  new_value=`{new_value}`\n  env_var=`{var}`\n  error=`{e}`")
-            });
+    }
+    let original = cli.fail_if_unchanged.then(|| documents.clone());
+    let targeted = xyaml::targeted_documents(&documents, config)?;
+    for &i in targeted.iter() {
+        xyaml::apply(&mut documents[i], config)?;
+    }
+    if let Some(schema) = &cli.schema {
+        for &i in targeted.iter() {
+            xyaml::schema::validate(&documents[i], schema)?;
         }
     }
+    let mut yaml = xyaml::render_documents(&documents, config)?;
+    if cli.preserve_comments {
+        yaml = xyaml::comments::restore_comments(yaml_strings, &yaml);
+    }
+    Ok(Transformed {
+        yaml,
+        exec_doc: targeted[0],
+        changed: original.map_or(true, |original| original != documents),
+        documents,
+    })
 }
 
-fn update_value(obj: &mut Value, path: &str, new_value: &str, require_null: bool) {
-    let segments: Value = serde_yaml::from_str(path)
-        .unwrap_or_else(|e| fail!("Failed to parse the path as YAML:\n`{path}`\nerror: {e}"));
-    if !segments.is_sequence() {
-        fail!("Path is not a YAML sequence:\n`{path}`")
-    }
-    let segments = segments.as_sequence().unwrap();
-    let mut cursor = vec![];
-    let mut current_obj = obj;
-    for segment in segments.iter() {
-        let segment_str = serde_yaml::to_string(segment)
-            .unwrap()
-            .trim_end_matches('\n')
-            .to_string();
-        cursor.push(segment_str.clone());
-
-        if segment.is_sequence() {
-            let seq = segment.as_sequence().unwrap();
-            if seq.len() != 1 {
-                fail!("Multiple sequence indexes are not supported\n  cursor=`{cursor:?}`\n  path=`{path}`");
+fn write_output(cli: &Cli, modified_yaml: &str) {
+    let config = &cli.config;
+    let output_path = match cli.in_place {
+        true => cli.inputs.first(),
+        false => cli.output.as_ref(),
+    };
+    if let Some(path) = output_path {
+        let content = cli.output_encoding.encode(modified_yaml);
+        xyaml::write_atomically(path, &content, cli.output_mode).unwrap_or_else(|e| exit_with(e));
+    } else if cli.exec.iter().any(|c| c.pipe_yaml) {
+        // The result is written into the stdin of the exec process.
+    } else if config.template.is_none()
+        && config.get.is_none()
+        && config.output_format == OutputFormat::Yaml
+    {
+        println!("{}", modified_yaml);
+    } else {
+        print!("{}", modified_yaml);
+    }
+}
+
+fn spawn_exec(cli: &Cli, command: &ExecCommand, yaml: &Value, modified_yaml: &str) -> Child {
+    let mut cmd = std::process::Command::new(&command.path);
+    let cmd = cmd.args(&command.args);
+    if let Some(cwd_path) = &cli.exec_cwd_from_yaml {
+        cmd.current_dir(exec_cwd(yaml, cwd_path));
+    }
+    if cli.exec_log_command {
+        eprintln!("exec: {}", command_line(&command.path, &command.args));
+    }
+    if command.pipe_yaml {
+        cmd.stdin(std::process::Stdio::piped());
+    } else if let Some(path) = &command.stdin {
+        let file = File::open(path).unwrap_or_else(
+            |e| fail!(Io: "Failed to open the exec stdin file `{path:?}`\nerror=`{e}`"),
+        );
+        cmd.stdin(std::process::Stdio::from(file));
+    }
+    if cli.exec_env_clear {
+        cmd.env_clear();
+    }
+    if let Some(prefix) = &cli.exec_yaml_env_prefix {
+        let vars = output::shell_vars(yaml).into_iter().map(|(name, value)| {
+            match prefix.is_empty() || prefix.ends_with('_') {
+                true => (format!("{prefix}{name}"), value),
+                false => (format!("{prefix}_{name}"), value),
             }
-            let idx = seq.first().unwrap();
-            if !idx.is_u64() {
-                fail!("Invalid sequence index `{idx:?}`\n  cursor=`{cursor:?}`\n  path=`{path}`");
+        });
+        cmd.envs(
+            vars.filter(|(name, _)| cli.exec_yaml_env_override || std::env::var_os(name).is_none()),
+        );
+    }
+    let mut handle = cmd
+        .spawn()
+        .unwrap_or_else(|e| fail!("Failed to spawn the process:\ncmd=`{cmd:?}`\nerror=`{e}`"));
+    if let Some(pid_file) = &cli.exec_pid_file {
+        std::fs::write(pid_file, format!("{}\n", handle.id())).unwrap_or_else(|e| {
+            handle.kill().ok();
+            fail!(Io: "Failed to write the PID file `{pid_file:?}`\nerror=`{e}`")
+        });
+    }
+    if let Some(mut stdin) = handle.stdin.take() {
+        // The process may exit without reading its stdin, which is fine.
+        if let Err(e) = stdin.write_all(modified_yaml.as_bytes()) {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                warn!("Warning: Failed to write the result into the stdin of the process: {e}");
             }
-            let idx = idx.as_u64().unwrap();
-            current_obj = current_obj.get_mut(idx as usize).unwrap_or_else(|| {
-                fail!("No entry at index {idx}\n  cursor=`{cursor:?}`\n  path=`{path}`")
-            });
-        } else {
-            current_obj = current_obj.get_mut(segment).unwrap_or_else(|| {
-                fail!("No key `{segment_str}`\n  cursor=`{cursor:?}`\n  path=`{path}`")
-            });
         }
     }
-    if require_null && !current_obj.is_null() {
-        fail!("Object at path is not `null`:\n  obj={current_obj:?}\n  path=`{path}`");
+    handle
+}
+
+/// How often the input file is checked for changes with `--watch`.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Re-applies the transformations whenever the input file changes and
+/// restarts the exec process, never returns. The errors are reported and
+/// the next change is awaited.
+fn watch(cli: &Cli) -> ! {
+    // The modification times and the sizes identify the input files versions,
+    // `None` if a file does not exist, e.g. while it is being replaced.
+    let stamp = || {
+        cli.inputs
+            .iter()
+            .map(|input| {
+                std::fs::metadata(input)
+                    .ok()
+                    .map(|m| (m.modified().ok(), m.len()))
+            })
+            .collect::<Option<Vec<_>>>()
+    };
+    // The last exec process and the time it was started at.
+    let mut child: Option<(Child, Instant)> = None;
+    loop {
+        let mut last = stamp();
+        match read_inputs(&cli.inputs).and_then(|yamls| transform_input(cli, &yamls)) {
+            Ok(t) => {
+                write_output(cli, &t.yaml);
+                if let Some((last, preceding)) = cli.exec.split_last() {
+                    if let Some((mut previous, _)) = child.take() {
+                        previous.kill().ok();
+                        previous.wait().ok();
+                    }
+                    let yaml = &t.documents[t.exec_doc];
+                    // Only the last process is kept running until the next
+                    // change, the preceding ones, e.g. validations, must
+                    // succeed first.
+                    let succeeded = preceding.iter().all(|command| {
+                        let mut handle = spawn_exec(cli, command, yaml, &t.yaml);
+                        match wait_exec(&mut handle, command, cli.exec_timeout) {
+                            Ok(Some(status)) if status.success() => true,
+                            Ok(None) => false,
+                            Ok(Some(status)) => {
+                                eprintln!(
+                                    "The process exited with the code {}:\ncmd=`{}`",
+                                    exit_code(status),
+                                    command_line(&command.path, &command.args)
+                                );
+                                false
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "Failed to wait for the process:\ncmd=`{}`\nerror=`{e}`",
+                                    command_line(&command.path, &command.args)
+                                );
+                                false
+                            }
+                        }
+                    });
+                    if succeeded {
+                        child = Some((spawn_exec(cli, last, yaml, &t.yaml), Instant::now()));
+                    }
+                }
+            }
+            Err(e) => print_error(e.message()),
+        }
+        loop {
+            std::thread::sleep(WATCH_POLL_INTERVAL);
+            if let (Some(timeout), Some((handle, started))) = (cli.exec_timeout, &mut child) {
+                if started.elapsed() >= timeout && matches!(handle.try_wait(), Ok(None)) {
+                    terminate(handle, cli.exec.last().unwrap(), timeout);
+                    child = None;
+                }
+            }
+            let mut current = stamp();
+            if current == last {
+                continue;
+            }
+            // Wait for the rapid successive changes to settle.
+            loop {
+                std::thread::sleep(cli.watch_debounce);
+                let next = stamp();
+                if next == current {
+                    break;
+                }
+                current = next;
+            }
+            last = current;
+            if last.is_some() {
+                break;
+            }
+        }
     }
-    *current_obj = serde_yaml::from_str(new_value).unwrap_or_else(|e| {
-        fail!("New value is no a valid YAML:\n  new_value=`{new_value}`\n  path=`{path}`\n  error=`{e}`")
-    });
+}
+
+/// The exit code when the exec process times out, as of `timeout(1)`.
+const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// How long a process may handle `SIGTERM` before it is killed.
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How often the exec process is checked for exiting with `--timeout`.
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Waits for the process to exit, terminating it if it runs longer than the
+/// timeout. Returns `None` if it was terminated.
+fn wait_exec(
+    handle: &mut Child,
+    command: &ExecCommand,
+    timeout: Option<Duration>,
+) -> io::Result<Option<std::process::ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return handle.wait().map(Some);
+    };
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = handle.try_wait()? {
+            return Ok(Some(status));
+        }
+        std::thread::sleep(EXEC_POLL_INTERVAL);
+    }
+    terminate(handle, command, timeout);
+    Ok(None)
+}
+
+/// Sends `SIGTERM` to the timed out process and kills it if it is still
+/// running after [`KILL_GRACE_PERIOD`].
+fn terminate(handle: &mut Child, command: &ExecCommand, timeout: Duration) {
+    eprintln!(
+        "The process did not exit in {} seconds, terminating it:\ncmd=`{}`",
+        timeout.as_secs(),
+        command_line(&command.path, &command.args)
+    );
+    // There is no `SIGTERM` in `std`, `kill(1)` sends it.
+    #[cfg(unix)]
+    {
+        let term = std::process::Command::new("kill")
+            .args(["-TERM", &handle.id().to_string()])
+            .status();
+        if term.is_ok_and(|status| status.success()) {
+            let deadline = Instant::now() + KILL_GRACE_PERIOD;
+            while Instant::now() < deadline {
+                if !matches!(handle.try_wait(), Ok(None)) {
+                    return;
+                }
+                std::thread::sleep(EXEC_POLL_INTERVAL);
+            }
+        }
+    }
+    handle.kill().ok();
+    handle.wait().ok();
+}
+
+/// Returns the exit code of the process, or 128 + the signal number if it
+/// was killed by a signal, as the shells do.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
 }
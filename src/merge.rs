@@ -0,0 +1,149 @@
+//! Deep merge of YAML documents.
+
+use crate::parse_path;
+use crate::PathSegment;
+use crate::Result;
+use serde_yaml::Value;
+
+/// How the overlay sequences are merged into the base sequences.
+#[derive(Clone, Copy, Default)]
+pub enum SequenceMerge {
+    #[default]
+    Replace,
+    Append,
+    /// The overlay mappings are deep-merged into the base mappings with the
+    /// same `name`, the others are appended.
+    ByName,
+    /// The overlay elements are deep-merged into the base elements at the
+    /// same indexes, the extra ones are appended.
+    ByIndex,
+}
+
+impl SequenceMerge {
+    pub const NAMES: [&'static str; 4] = ["replace", "append", "by-name", "by-index"];
+
+    pub fn from_name(name: &str) -> SequenceMerge {
+        match name {
+            "append" => SequenceMerge::Append,
+            "by-name" => SequenceMerge::ByName,
+            "by-index" => SequenceMerge::ByIndex,
+            _ => SequenceMerge::Replace,
+        }
+    }
+}
+
+/// Merges the overlay into the base: mappings are merged recursively,
+/// sequences are merged according to `sequences`, any other overlay value
+/// replaces the base value.
+pub fn deep_merge(base: &mut Value, overlay: &Value, sequences: SequenceMerge) {
+    merge_at(base, overlay, sequences, &[], &mut vec![]);
+}
+
+/// Replaces the top-level entries of the base mapping with the ones of the
+/// overlay mapping without merging them. Any other overlay value replaces the
+/// base value.
+pub fn shallow_merge(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay.iter() {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// Like [`deep_merge`], but the sequences at the paths of `strategies` are
+/// merged according to their strategies instead. The paths may have `*`
+/// segments, the last matching path wins.
+pub fn deep_merge_with(
+    base: &mut Value,
+    overlay: &Value,
+    sequences: SequenceMerge,
+    strategies: &[(String, SequenceMerge)],
+) -> Result<()> {
+    let strategies = strategies
+        .iter()
+        .map(|(path, strategy)| Ok((parse_path(path)?, *strategy)))
+        .collect::<Result<Vec<_>>>()?;
+    merge_at(base, overlay, sequences, &strategies, &mut vec![]);
+    Ok(())
+}
+
+fn merge_at(
+    base: &mut Value,
+    overlay: &Value,
+    sequences: SequenceMerge,
+    strategies: &[(Vec<PathSegment>, SequenceMerge)],
+    path: &mut Vec<PathSegment>,
+) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay.iter() {
+                match base.get_mut(key) {
+                    Some(base_value) => {
+                        path.push(PathSegment::Key(key.clone()));
+                        merge_at(base_value, value, sequences, strategies, path);
+                        path.pop();
+                    }
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(overlay)) => {
+            let strategy = strategies
+                .iter()
+                .rev()
+                .find(|(pattern, _)| matches_path(pattern, path))
+                .map_or(sequences, |(_, strategy)| *strategy);
+            match strategy {
+                SequenceMerge::Replace => *base = overlay.clone(),
+                SequenceMerge::Append => base.extend(overlay.iter().cloned()),
+                SequenceMerge::ByName => {
+                    for value in overlay {
+                        let name = value.get("name").filter(|_| value.is_mapping());
+                        let position = name.and_then(|name| {
+                            base.iter()
+                                .position(|v| v.is_mapping() && v.get("name") == Some(name))
+                        });
+                        match position {
+                            Some(i) => {
+                                path.push(PathSegment::Index(i as i64));
+                                merge_at(&mut base[i], value, sequences, strategies, path);
+                                path.pop();
+                            }
+                            None => base.push(value.clone()),
+                        }
+                    }
+                }
+                SequenceMerge::ByIndex => {
+                    for (i, value) in overlay.iter().enumerate() {
+                        match base.get_mut(i) {
+                            Some(base_value) => {
+                                path.push(PathSegment::Index(i as i64));
+                                merge_at(base_value, value, sequences, strategies, path);
+                                path.pop();
+                            }
+                            None => base.push(value.clone()),
+                        }
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+fn matches_path(pattern: &[PathSegment], path: &[PathSegment]) -> bool {
+    pattern.len() == path.len()
+        && pattern.iter().zip(path).all(|(p, s)| match (p, s) {
+            (PathSegment::Wildcard, _) => true,
+            (PathSegment::Key(p), PathSegment::Key(s)) => p == s,
+            (PathSegment::Index(p), PathSegment::Index(s)) => p == s,
+            // Dot-separated paths parse all-digit keys as indexes.
+            (PathSegment::Index(p), PathSegment::Key(s)) => s.as_i64() == Some(*p),
+            _ => false,
+        })
+}
@@ -0,0 +1,778 @@
+//! Alternative output formats for the resulting YAML document.
+
+use crate::Result;
+use serde_yaml::value::TaggedValue;
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+use std::path::Path;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Yaml,
+    AwsSsm,
+    KubernetesConfigMap,
+    Json,
+    Shell,
+    Toml,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&'static str; 6] = [
+        "yaml",
+        "aws-ssm",
+        "kubernetes-configmap",
+        "json",
+        "shell",
+        "toml",
+    ];
+
+    pub fn from_name(name: &str) -> OutputFormat {
+        match name {
+            "aws-ssm" => OutputFormat::AwsSsm,
+            "kubernetes-configmap" => OutputFormat::KubernetesConfigMap,
+            "json" => OutputFormat::Json,
+            "shell" => OutputFormat::Shell,
+            "toml" => OutputFormat::Toml,
+            _ => OutputFormat::Yaml,
+        }
+    }
+
+    /// Returns the format of the file extension, if known.
+    pub fn from_extension(path: &Path) -> Option<OutputFormat> {
+        match path.extension()?.to_str()? {
+            "json" => Some(OutputFormat::Json),
+            "toml" => Some(OutputFormat::Toml),
+            "yaml" | "yml" => Some(OutputFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// The character encoding of the output files.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputEncoding {
+    #[default]
+    Utf8,
+    /// UTF-8 with the byte order mark.
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl OutputEncoding {
+    pub const NAMES: [&'static str; 4] = ["utf-8", "utf-8-bom", "utf-16-le", "utf-16-be"];
+
+    pub fn from_name(name: &str) -> OutputEncoding {
+        match name {
+            "utf-8-bom" => OutputEncoding::Utf8Bom,
+            "utf-16-le" => OutputEncoding::Utf16Le,
+            "utf-16-be" => OutputEncoding::Utf16Be,
+            _ => OutputEncoding::Utf8,
+        }
+    }
+
+    /// Encodes the text, the UTF-16 encodings start with the byte order mark.
+    pub fn encode(&self, s: &str) -> Vec<u8> {
+        let utf16 = || std::iter::once(0xFEFF).chain(s.encode_utf16());
+        match self {
+            OutputEncoding::Utf8 => s.as_bytes().to_vec(),
+            OutputEncoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF], s.as_bytes()].concat(),
+            OutputEncoding::Utf16Le => utf16().flat_map(u16::to_le_bytes).collect(),
+            OutputEncoding::Utf16Be => utf16().flat_map(u16::to_be_bytes).collect(),
+        }
+    }
+}
+
+/// The representation of the `null` values in the YAML output.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullStyle {
+    #[default]
+    Null,
+    Tilde,
+    /// No value, e.g. `key:` and `-` in the block style.
+    Empty,
+    /// Drops the mapping entries with the `null` values.
+    Omit,
+}
+
+impl NullStyle {
+    pub const NAMES: [&'static str; 4] = ["null", "tilde", "empty", "omit"];
+
+    pub fn from_name(name: &str) -> NullStyle {
+        match name {
+            "tilde" => NullStyle::Tilde,
+            "empty" => NullStyle::Empty,
+            "omit" => NullStyle::Omit,
+            _ => NullStyle::Null,
+        }
+    }
+}
+
+/// Serializes the document in the YAML block style.
+pub fn to_yaml(v: &Value) -> Result<String> {
+    serde_yaml::to_string(v).map_err(|e| error!("Failed to serialize YAML: {e}"))
+}
+
+/// Serializes the document with `serialize` writing the `null` values in the
+/// style. The values are replaced with a marker string absent from the
+/// document before the serialization and the marker is replaced after it.
+/// `empty` falls back to `null` where the value is not at the end of the
+/// line, e.g. within flow collections.
+pub fn to_yaml_with_nulls(
+    v: &Value,
+    style: NullStyle,
+    serialize: impl Fn(&Value) -> Result<String>,
+) -> Result<String> {
+    let replacement = match style {
+        NullStyle::Null => return serialize(v),
+        NullStyle::Omit => return serialize(&omit_nulls(v)),
+        NullStyle::Tilde => "~",
+        NullStyle::Empty => "null",
+    };
+    let plain = serialize(v)?;
+    let mut marker = "xyaml-null".to_string();
+    while plain.contains(&marker) {
+        marker.push('_');
+    }
+    let marked = serialize(&replace_nulls(v, &marker))?;
+    let mut result = String::with_capacity(plain.len());
+    for line in marked.split_inclusive('\n') {
+        let content = line.trim_end_matches('\n');
+        let content = match style {
+            NullStyle::Empty => content
+                .strip_suffix(&format!(" {marker}"))
+                .unwrap_or(content),
+            _ => content,
+        };
+        result.push_str(&content.replace(&marker, replacement));
+        result.push_str(&line[line.trim_end_matches('\n').len()..]);
+    }
+    Ok(result)
+}
+
+fn omit_nulls(v: &Value) -> Value {
+    match v {
+        Value::Mapping(map) => Value::Mapping(
+            map.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), omit_nulls(value)))
+                .collect(),
+        ),
+        Value::Sequence(seq) => Value::Sequence(seq.iter().map(omit_nulls).collect()),
+        Value::Tagged(tagged) => Value::Tagged(Box::new(TaggedValue {
+            tag: tagged.tag.clone(),
+            value: omit_nulls(&tagged.value),
+        })),
+        v => v.clone(),
+    }
+}
+
+fn replace_nulls(v: &Value, marker: &str) -> Value {
+    match v {
+        Value::Null => Value::String(marker.to_string()),
+        Value::Mapping(map) => Value::Mapping(
+            map.iter()
+                .map(|(key, value)| (key.clone(), replace_nulls(value, marker)))
+                .collect(),
+        ),
+        Value::Sequence(seq) => Value::Sequence(
+            seq.iter()
+                .map(|value| replace_nulls(value, marker))
+                .collect(),
+        ),
+        Value::Tagged(tagged) => Value::Tagged(Box::new(TaggedValue {
+            tag: tagged.tag.clone(),
+            value: replace_nulls(&tagged.value, marker),
+        })),
+        v => v.clone(),
+    }
+}
+
+#[derive(Default)]
+pub struct SsmOptions {
+    pub prefix: String,
+    pub secure_paths: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ConfigMapOptions {
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    pub key: Option<String>,
+}
+
+/// Collects the scalar leaves of the document together with the keys and
+/// indexes leading to them. Empty mappings and sequences have no leaves.
+pub fn flatten_leaves(v: &Value) -> Vec<(Vec<String>, &Value)> {
+    let mut leaves = vec![];
+    do_flatten_leaves(v, &mut vec![], &mut leaves);
+    leaves
+}
+
+fn do_flatten_leaves<'a>(
+    v: &'a Value,
+    prefix: &mut Vec<String>,
+    leaves: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    match v {
+        Value::Mapping(map) => {
+            for (key, v) in map.iter() {
+                prefix.push(scalar_to_string(key));
+                do_flatten_leaves(v, prefix, leaves);
+                prefix.pop();
+            }
+        }
+        Value::Sequence(seq) => {
+            for (idx, v) in seq.iter().enumerate() {
+                prefix.push(idx.to_string());
+                do_flatten_leaves(v, prefix, leaves);
+                prefix.pop();
+            }
+        }
+        Value::Tagged(tagged) => do_flatten_leaves(&tagged.value, prefix, leaves),
+        _ => leaves.push((prefix.clone(), v)),
+    }
+}
+
+/// Returns the plain text representation of a scalar, e.g. `5432` or `true`
+/// without any YAML quoting. Complex values are serialized as flow YAML.
+pub fn scalar_to_string(v: &Value) -> String {
+    match v {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Tagged(tagged) => scalar_to_string(&tagged.value),
+        v => to_flow_yaml(v).trim_end().to_string(),
+    }
+}
+
+/// Wraps the string in double quotes escaping the characters the shell
+/// interprets inside them.
+pub fn shell_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Matches the text against a glob pattern where `*` matches any sequence of
+/// characters and `?` matches a single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((bp, bt)) = backtrack {
+            p = bp + 1;
+            t = bt + 1;
+            backtrack = Some((bp, bt + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Renders `aws ssm put-parameter` commands for every scalar leaf. The
+/// parameter names are the leaf paths joined with `/` under the prefix.
+/// `null` leaves are skipped since SSM does not accept empty values.
+pub fn to_aws_ssm(v: &Value, options: &SsmOptions) -> String {
+    let prefix = options.prefix.trim_matches('/');
+    let mut commands = String::new();
+    for (path, leaf) in flatten_leaves(v) {
+        let name = path.join("/");
+        if leaf.is_null() {
+            warn!("Skipping the `null` value of the SSM parameter `{name}`");
+            continue;
+        }
+        let kind = if options.secure_paths.iter().any(|g| glob_match(g, &name)) {
+            "SecureString"
+        } else {
+            "String"
+        };
+        let full_name = if prefix.is_empty() {
+            format!("/{name}")
+        } else {
+            format!("/{prefix}/{name}")
+        };
+        commands.push_str(&format!(
+            "aws ssm put-parameter --name {} --value {} --type {kind}\n",
+            shell_quote(&full_name),
+            shell_quote(&scalar_to_string(leaf)),
+        ));
+    }
+    commands
+}
+
+/// Renders a `NAME="value"` shell assignment for every variable of
+/// [`shell_vars`].
+pub fn to_shell(v: &Value) -> String {
+    shell_vars(v)
+        .into_iter()
+        .map(|(name, value)| format!("{name}={}\n", shell_quote(&value)))
+        .collect()
+}
+
+/// Collects a variable for every scalar leaf of the nested mappings. The
+/// names are the uppercased keys joined with `_`. Sequences are skipped since
+/// environment variables cannot hold them.
+pub fn shell_vars(v: &Value) -> Vec<(String, String)> {
+    let mut vars = vec![];
+    do_shell_vars(v, &mut vec![], &mut vars);
+    vars
+}
+
+fn do_shell_vars(v: &Value, prefix: &mut Vec<String>, vars: &mut Vec<(String, String)>) {
+    match v {
+        Value::Mapping(map) => {
+            for (key, v) in map.iter() {
+                prefix.push(scalar_to_string(key));
+                do_shell_vars(v, prefix, vars);
+                prefix.pop();
+            }
+        }
+        Value::Tagged(tagged) => do_shell_vars(&tagged.value, prefix, vars),
+        Value::Sequence(_) => {
+            warn!("Skipping the sequence at `{}`", prefix.join("."));
+        }
+        _ if prefix.is_empty() => warn!("Skipping the scalar document"),
+        v => {
+            let name: String = prefix
+                .join("_")
+                .chars()
+                .map(|c| match c.is_ascii_alphanumeric() {
+                    true => c.to_ascii_uppercase(),
+                    false => '_',
+                })
+                .collect();
+            vars.push((name, scalar_to_string(v)));
+        }
+    }
+}
+
+/// Wraps the document into a Kubernetes ConfigMap manifest. The data entries
+/// are the scalar leaves with the paths joined with `.` as the keys, or the
+/// whole document as YAML under the single `key` if it is given.
+pub fn to_kubernetes_configmap(v: &Value, options: &ConfigMapOptions) -> Result<String> {
+    let name = options.name.as_ref().ok_or_else(|| {
+        error!("`--configmap-name` is required for the `kubernetes-configmap` output format")
+    })?;
+    let mut metadata = Mapping::new();
+    metadata.insert("name".into(), name.as_str().into());
+    if let Some(namespace) = &options.namespace {
+        metadata.insert("namespace".into(), namespace.as_str().into());
+    }
+    let mut data = Mapping::new();
+    if let Some(key) = &options.key {
+        let yaml = to_yaml(v)?;
+        data.insert(key.as_str().into(), yaml.into());
+    } else {
+        for (path, leaf) in flatten_leaves(v) {
+            data.insert(path.join(".").into(), scalar_to_string(leaf).into());
+        }
+    }
+    let mut manifest = Mapping::new();
+    manifest.insert("apiVersion".into(), "v1".into());
+    manifest.insert("kind".into(), "ConfigMap".into());
+    manifest.insert("metadata".into(), metadata.into());
+    manifest.insert("data".into(), data.into());
+    to_yaml(&manifest.into())
+}
+
+/// Inserts a `# path: [...]` comment above every line of the serialized YAML
+/// holding a scalar leaf of the document. Returns the YAML unchanged if the
+/// lines cannot be matched with the leaves, e.g. for complex mapping keys.
+pub fn add_path_comments(yaml: &str, doc: &Value) -> String {
+    let mut paths = vec![];
+    collect_line_leaf_paths(doc, &mut vec![], &mut paths);
+    let mut paths = paths.into_iter();
+    let mut result = String::with_capacity(yaml.len() * 2);
+    let mut block_threshold: Option<usize> = None;
+    for line in yaml.split_inclusive('\n') {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if let Some(threshold) = block_threshold {
+            if line.trim().is_empty() || indent > threshold {
+                result.push_str(line);
+                continue;
+            }
+            block_threshold = None;
+        }
+        let mut rest = line[indent..].trim_end_matches('\n');
+        let mut content_indent = indent;
+        while let Some(r) = rest.strip_prefix("- ") {
+            rest = r;
+            content_indent += 2;
+        }
+        if rest.starts_with('?') {
+            return skip_path_comments(yaml);
+        }
+        let (is_leaf, value, threshold) = match key_value_split(rest) {
+            Some(value) => {
+                let is_parent = value.is_empty() || value.starts_with('!') && !value.contains(' ');
+                (!is_parent, value, content_indent)
+            }
+            None => (true, rest, content_indent.saturating_sub(2)),
+        };
+        if is_leaf {
+            let Some(path) = paths.next() else {
+                return skip_path_comments(yaml);
+            };
+            result.push_str(&format!(
+                "{}# path: [{}]\n",
+                " ".repeat(indent),
+                path.join(", ")
+            ));
+            if value.starts_with(['|', '>']) {
+                block_threshold = Some(threshold);
+            }
+        }
+        result.push_str(line);
+    }
+    if paths.next().is_some() {
+        return skip_path_comments(yaml);
+    }
+    result
+}
+
+fn skip_path_comments(yaml: &str) -> String {
+    warn!("Failed to match the YAML lines with the paths, skipping the path comments");
+    yaml.to_string()
+}
+
+/// Returns the value part of a `key: value` line, or `None` if the line is
+/// not a mapping entry.
+fn key_value_split(line: &str) -> Option<&str> {
+    split_key_value(line).map(|(_, value)| value)
+}
+
+/// Splits a `key: value` line into the key as written and the value, or
+/// returns `None` if the line is not a mapping entry.
+pub(crate) fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let key_end = match line.chars().next() {
+        Some(q @ ('\'' | '"')) => {
+            let mut escaped = false;
+            let mut end = None;
+            for (i, c) in line.char_indices().skip(1) {
+                if escaped {
+                    escaped = false;
+                } else if q == '"' && c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            end?
+        }
+        _ => line
+            .find(": ")
+            .or_else(|| line.strip_suffix(':').map(str::len))?,
+    };
+    let value = line[key_end..].strip_prefix(':')?;
+    if !(value.is_empty() || value.starts_with(' ')) {
+        return None;
+    }
+    Some((&line[..key_end], value.trim()))
+}
+
+/// Collects the paths of the values serialized on a single line: scalars,
+/// empty mappings and empty sequences.
+fn collect_line_leaf_paths(v: &Value, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match v {
+        Value::Mapping(map) if !map.is_empty() => {
+            for (key, v) in map.iter() {
+                prefix.push(to_flow_yaml(key).trim_end().to_string());
+                collect_line_leaf_paths(v, prefix, paths);
+                prefix.pop();
+            }
+        }
+        Value::Sequence(seq) if !seq.is_empty() => {
+            for (idx, v) in seq.iter().enumerate() {
+                prefix.push(format!("[{idx}]"));
+                collect_line_leaf_paths(v, prefix, paths);
+                prefix.pop();
+            }
+        }
+        Value::Tagged(tagged)
+            if matches!(&tagged.value, Value::Mapping(m) if !m.is_empty())
+                || matches!(&tagged.value, Value::Sequence(s) if !s.is_empty()) =>
+        {
+            collect_line_leaf_paths(&tagged.value, prefix, paths)
+        }
+        _ => paths.push(prefix.clone()),
+    }
+}
+
+/// Serializes the document on a single line in the YAML flow style, e.g.
+/// `{a: 1, b: [2, {c: 3}]}`.
+pub fn to_flow_yaml(v: &Value) -> String {
+    let mut out = String::new();
+    write_flow(v, &mut out);
+    out.push('\n');
+    out
+}
+
+fn write_flow(v: &Value, out: &mut String) {
+    match v {
+        Value::Mapping(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_flow(key, out);
+                out.push_str(": ");
+                write_flow(value, out);
+            }
+            out.push('}');
+        }
+        Value::Sequence(seq) => {
+            out.push('[');
+            for (i, value) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_flow(value, out);
+            }
+            out.push(']');
+        }
+        Value::Tagged(tagged) => {
+            out.push_str(&tagged.tag.to_string());
+            out.push(' ');
+            write_flow(&tagged.value, out);
+        }
+        Value::String(s) => {
+            // Serializing a string cannot fail, the empty representation
+            // falls back to the double quotes anyway.
+            let repr = serde_yaml::to_string(v).unwrap_or_default();
+            let repr = repr.trim_end_matches('\n');
+            let is_plain = !repr.starts_with(['\'', '"']);
+            if repr.is_empty()
+                || repr.contains('\n')
+                || is_plain && repr.contains([',', '[', ']', '{', '}'])
+            {
+                out.push_str(&double_quote(s));
+            } else {
+                out.push_str(repr);
+            }
+        }
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        // The numbers are displayed in the YAML notation, e.g. `.inf`.
+        Value::Number(n) => out.push_str(&n.to_string()),
+    }
+}
+
+/// Serializes the document as pretty-printed JSON. Non-string scalar keys are
+/// converted to strings, tags are dropped.
+pub fn to_json(v: &Value) -> Result<String> {
+    let mut out = String::new();
+    write_json(v, 0, &mut out)?;
+    out.push('\n');
+    Ok(out)
+}
+
+fn write_json(v: &Value, indent: usize, out: &mut String) -> Result<()> {
+    let pad = "  ".repeat(indent + 1);
+    match v {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) if n.as_f64().is_some_and(|f| !f.is_finite()) => {
+            bail!("The number `{n}` cannot be represented in JSON")
+        }
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => out.push_str(&double_quote(s)),
+        Value::Sequence(seq) if seq.is_empty() => out.push_str("[]"),
+        Value::Sequence(seq) => {
+            out.push_str("[\n");
+            for (i, value) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                out.push_str(&pad);
+                write_json(value, indent + 1, out)?;
+            }
+            out.push('\n');
+            out.push_str(&pad[2..]);
+            out.push(']');
+        }
+        Value::Mapping(map) if map.is_empty() => out.push_str("{}"),
+        Value::Mapping(map) => {
+            out.push_str("{\n");
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                if key.is_mapping() || key.is_sequence() {
+                    bail!("The complex mapping key `{key:?}` cannot be represented in JSON");
+                }
+                out.push_str(&pad);
+                out.push_str(&double_quote(&scalar_to_string(key)));
+                out.push_str(": ");
+                write_json(value, indent + 1, out)?;
+            }
+            out.push('\n');
+            out.push_str(&pad[2..]);
+            out.push('}');
+        }
+        Value::Tagged(tagged) => write_json(&tagged.value, indent, out)?,
+    }
+    Ok(())
+}
+
+/// Serializes the document as TOML. Mappings become tables and sequences of
+/// mappings become arrays of tables, nested ones are inlined. Fails on `null`
+/// values and non-mapping documents since TOML cannot represent them.
+pub fn to_toml(v: &Value) -> Result<String> {
+    let Value::Mapping(map) = untag(v) else {
+        bail!("Only a mapping document can be represented in TOML");
+    };
+    let mut out = String::new();
+    write_toml_table(map, &mut vec![], &mut out)?;
+    Ok(out)
+}
+
+fn untag(v: &Value) -> &Value {
+    match v {
+        Value::Tagged(tagged) => untag(&tagged.value),
+        v => v,
+    }
+}
+
+fn is_toml_table_array(v: &Value) -> bool {
+    match untag(v) {
+        Value::Sequence(seq) => !seq.is_empty() && seq.iter().all(|v| untag(v).is_mapping()),
+        _ => false,
+    }
+}
+
+fn write_toml_table(map: &Mapping, path: &mut Vec<String>, out: &mut String) -> Result<()> {
+    // The plain values must precede the sub-tables, otherwise they would
+    // belong to the last sub-table.
+    for (key, value) in map.iter() {
+        if untag(value).is_mapping() || is_toml_table_array(value) {
+            continue;
+        }
+        path.push(toml_key(key)?);
+        out.push_str(&format!("{} = ", path.last().unwrap()));
+        write_toml_value(value, path, out)?;
+        out.push('\n');
+        path.pop();
+    }
+    for (key, value) in map.iter() {
+        path.push(toml_key(key)?);
+        match untag(value) {
+            Value::Mapping(table) => {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", path.join(".")));
+                write_toml_table(table, path, out)?;
+            }
+            Value::Sequence(tables) if is_toml_table_array(value) => {
+                for table in tables {
+                    if !out.is_empty() {
+                        out.push('\n');
+                    }
+                    out.push_str(&format!("[[{}]]\n", path.join(".")));
+                    write_toml_table(untag(table).as_mapping().unwrap(), path, out)?;
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn toml_key(key: &Value) -> Result<String> {
+    if key.is_mapping() || key.is_sequence() {
+        bail!("The complex mapping key `{key:?}` cannot be represented in TOML");
+    }
+    let key = scalar_to_string(key);
+    let is_bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    Ok(match is_bare {
+        true => key,
+        false => double_quote(&key),
+    })
+}
+
+fn write_toml_value(v: &Value, path: &mut Vec<String>, out: &mut String) -> Result<()> {
+    match v {
+        Value::Null => bail!(
+            "The `null` value cannot be represented in TOML\n  path=`{}`",
+            path.join(".")
+        ),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if f.is_nan() => out.push_str("nan"),
+            Some(f) if f.is_infinite() => out.push_str(if f > 0.0 { "inf" } else { "-inf" }),
+            _ => out.push_str(&n.to_string()),
+        },
+        Value::String(s) => out.push_str(&double_quote(s)),
+        Value::Sequence(seq) => {
+            out.push('[');
+            for (i, value) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                path.push(i.to_string());
+                write_toml_value(value, path, out)?;
+                path.pop();
+            }
+            out.push(']');
+        }
+        Value::Mapping(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                path.push(toml_key(key)?);
+                out.push_str(&format!("{} = ", path.last().unwrap()));
+                write_toml_value(value, path, out)?;
+                path.pop();
+            }
+            out.push('}');
+        }
+        Value::Tagged(tagged) => write_toml_value(&tagged.value, path, out)?,
+    }
+    Ok(())
+}
+
+/// Quotes the string as a JSON string, which is also a TOML basic string.
+pub fn double_quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,97 @@
+//! Named argument presets for `--profile`.
+//!
+//! Profiles are looked up in `.xyaml.yaml` in the current directory and then
+//! in `$XDG_CONFIG_HOME/xyaml/profiles.yaml` (`~/.config/xyaml/profiles.yaml`
+//! by default). Both files map profile names to profiles:
+//!
+//! ```yaml
+//! base:
+//!   args: [--require-null, --set, '[db, host]', localhost]
+//! prod:
+//!   extends: base
+//!   args: [--set, '[db, port]', '5432']
+//! ```
+//!
+//! The arguments of the extended profile precede the profile's own ones.
+
+use serde_yaml::Value;
+use std::path::PathBuf;
+use xyaml::output::scalar_to_string;
+
+fn profile_files() -> Vec<PathBuf> {
+    let mut files = vec![PathBuf::from(".xyaml.yaml")];
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = config_dir {
+        files.push(dir.join("xyaml").join("profiles.yaml"));
+    }
+    files
+}
+
+fn load_profiles() -> Vec<(PathBuf, Value)> {
+    profile_files()
+        .into_iter()
+        .filter(|path| path.is_file())
+        .map(|path| {
+            let content = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+                fail!("Failed to read the profiles file `{path:?}`\nerror=`{e}`")
+            });
+            let profiles = serde_yaml::from_str(&content).unwrap_or_else(|e| {
+                fail!("Failed to parse the profiles file `{path:?}` as YAML: {e}")
+            });
+            (path, profiles)
+        })
+        .collect()
+}
+
+/// Returns the arguments of the profile including the inherited ones.
+pub fn profile_args(name: &str) -> Vec<String> {
+    let profiles = load_profiles();
+    let mut chain = vec![];
+    let mut args = vec![];
+    collect_args(&profiles, name, &mut chain, &mut args);
+    args
+}
+
+fn collect_args(
+    profiles: &[(PathBuf, Value)],
+    name: &str,
+    chain: &mut Vec<String>,
+    args: &mut Vec<String>,
+) {
+    if chain.iter().any(|n| n == name) {
+        fail!(
+            "Cyclic profile inheritance: {} -> {name}",
+            chain.join(" -> ")
+        );
+    }
+    chain.push(name.to_string());
+    let (path, profile) = profiles
+        .iter()
+        .find_map(|(path, profiles)| profiles.get(name).map(|p| (path, p)))
+        .unwrap_or_else(|| {
+            fail!(
+                "No profile `{name}` in the profiles files {:?}",
+                profile_files()
+            )
+        });
+    if let Some(base) = profile.get("extends") {
+        let base = base.as_str().unwrap_or_else(|| {
+            fail!("The `extends` of the profile `{name}` in `{path:?}` is not a string")
+        });
+        collect_args(profiles, base, chain, args);
+    }
+    let own_args = match profile.get("args") {
+        None => &vec![],
+        Some(Value::Sequence(seq)) => seq,
+        Some(_) => fail!("The `args` of the profile `{name}` in `{path:?}` is not a sequence"),
+    };
+    for arg in own_args {
+        if arg.is_mapping() || arg.is_sequence() {
+            fail!("The argument `{arg:?}` of the profile `{name}` in `{path:?}` is not a scalar, quote it");
+        }
+        args.push(scalar_to_string(arg));
+    }
+    chain.pop();
+}
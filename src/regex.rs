@@ -0,0 +1,680 @@
+//! A small regular expression engine.
+//!
+//! The expressions are compiled into a program run by a Pike VM, which keeps
+//! all of the alternatives in step with the text instead of backtracking, so
+//! that the matching takes linear time and constant stack in the text length.
+//! The leftmost match is preferred and among the matches at the same position
+//! the one of the first alternative and the greedy quantifiers, as with the
+//! backtracking engines.
+//!
+//! Supported syntax: literals, `.`, `[...]` and `[^...]` classes with ranges,
+//! `\d \w \s \D \W \S \b \B` and escaped metacharacters, `^` and `$`
+//! anchors, `(...)`, `(?:...)`, `(?P<name>...)` and `(?<name>...)` groups,
+//! `|` alternation, and the `* + ? {n} {n,} {n,m}` quantifiers with their
+//! lazy `?` forms.
+//!
+//! Replacement strings may refer to the captured groups with `$1`, `${1}`,
+//! `$name` or `${name}`; `$$` is a literal `$`.
+
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    Group(Option<usize>, Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Clone)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(from, to) => *from <= c && c <= *to,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => is_word(c) != *negated,
+            ClassItem::Space(negated) => c.is_whitespace() != *negated,
+        }
+    }
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.items.iter().any(|i| i.matches(c)) != self.negated
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+type Captures = Vec<Option<(usize, usize)>>;
+
+/// The start and the end positions of the groups, the whole match first.
+type Slots = Vec<Option<usize>>;
+
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    /// Continues at both targets, the first one is preferred.
+    Split(usize, usize),
+    Jump(usize),
+    /// Records the position into the slot.
+    Save(usize),
+    Match,
+}
+
+pub struct Regex {
+    program: Vec<Inst>,
+    groups: usize,
+    names: Vec<(String, usize)>,
+}
+
+struct Parser<'p> {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    names: Vec<(String, usize)>,
+    pattern: &'p str,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> String {
+        format!(
+            "{msg} at position {} of the regex `{}`",
+            self.pos, self.pattern
+        )
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut alternatives = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alternatives.push(self.parse_concat()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Node::Alt(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.parse_counts() {
+                Some(counts) => counts,
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(
+            atom,
+            Node::Start | Node::End | Node::WordBoundary(_) | Node::Repeat { .. }
+        ) {
+            return Err(self.error("Invalid quantifier target"));
+        }
+        let greedy = !self.eat('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}` leaving the position at the closing
+    /// brace. Returns `None` and keeps the position if it is a literal `{`.
+    fn parse_counts(&mut self) -> Option<(usize, Option<usize>)> {
+        let rest: String = self.chars[self.pos + 1..].iter().collect();
+        let end = rest.find('}')?;
+        let body = &rest[..end];
+        let (min, max) = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        self.pos += end + 1;
+        Some((min, max))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '[' => Node::Class(self.parse_class()?),
+            '(' => self.parse_group()?,
+            '\\' => self.parse_escape(false)?,
+            '*' | '+' | '?' => return Err(self.error("Quantifier without a target")),
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_group(&mut self) -> Result<Node, String> {
+        let index = if self.eat('?') {
+            if self.eat(':') {
+                None
+            } else {
+                self.eat('P');
+                if !self.eat('<') {
+                    return Err(self.error("Unsupported group syntax"));
+                }
+                let mut name = String::new();
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '>' {
+                        break;
+                    }
+                    name.push(c);
+                }
+                self.groups += 1;
+                self.names.push((name, self.groups));
+                Some(self.groups)
+            }
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+        let inner = self.parse_alt()?;
+        if !self.eat(')') {
+            return Err(self.error("Unclosed group"));
+        }
+        Ok(Node::Group(index, Box::new(inner)))
+    }
+
+    fn parse_escape(&mut self, in_class: bool) -> Result<Node, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("Trailing backslash"))?;
+        self.pos += 1;
+        let class = |item| {
+            Node::Class(Class {
+                items: vec![item],
+                negated: false,
+            })
+        };
+        Ok(match c {
+            'd' => class(ClassItem::Digit(false)),
+            'D' => class(ClassItem::Digit(true)),
+            'w' => class(ClassItem::Word(false)),
+            'W' => class(ClassItem::Word(true)),
+            's' => class(ClassItem::Space(false)),
+            'S' => class(ClassItem::Space(true)),
+            'b' if !in_class => Node::WordBoundary(true),
+            'B' if !in_class => Node::WordBoundary(false),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            'r' => Node::Char('\r'),
+            c if c.is_alphanumeric() => {
+                return Err(self.error(&format!("Unsupported escape `\\{c}`")))
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, String> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        let mut first = true;
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("Unclosed character class"))?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let from = if c == '\\' {
+                match self.parse_escape(true)? {
+                    Node::Char(c) => c,
+                    Node::Class(mut class) => {
+                        items.append(&mut class.items);
+                        continue;
+                    }
+                    _ => unreachable!(),
+                }
+            } else {
+                c
+            };
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|c| *c != ']');
+            if is_range {
+                self.pos += 1;
+                let mut to = self.peek().unwrap();
+                self.pos += 1;
+                if to == '\\' {
+                    match self.parse_escape(true)? {
+                        Node::Char(c) => to = c,
+                        _ => return Err(self.error("Invalid class range")),
+                    }
+                }
+                if to < from {
+                    return Err(self.error("Invalid class range"));
+                }
+                items.push(ClassItem::Range(from, to));
+            } else {
+                items.push(ClassItem::Range(from, from));
+            }
+        }
+        Ok(Class { items, negated })
+    }
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            names: vec![],
+            pattern,
+        };
+        let root = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("Unmatched `)`"));
+        }
+        let mut program = vec![Inst::Save(0)];
+        compile(&root, &mut program);
+        program.extend([Inst::Save(1), Inst::Match]);
+        Ok(Regex {
+            program,
+            groups: parser.groups,
+            names: parser.names,
+        })
+    }
+
+    /// Returns `true` if the whole text matches the expression.
+    pub fn is_full_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.run(&chars, 0, true).is_some()
+    }
+
+    /// Returns `true` if the expression matches anywhere in the text.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        self.run(&chars, 0, false).is_some()
+    }
+
+    /// Returns the groups of the first match starting at or after the start.
+    fn captures_from(&self, chars: &[char], start: usize) -> Option<Captures> {
+        let slots = self.run(chars, start, false)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| Some((pair[0]?, pair[1]?)))
+                .collect(),
+        )
+    }
+
+    /// Replaces all non-overlapping matches, expanding the group references
+    /// in the replacement.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::new();
+        let mut pos = 0;
+        let mut last = 0;
+        let mut last_match_end = None;
+        while pos <= chars.len() {
+            let Some(caps) = self.captures_from(&chars, pos) else {
+                break;
+            };
+            let (start, end) = caps[0].unwrap();
+            // An empty match right after the previous match is not a new one.
+            if start == end && last_match_end == Some(start) {
+                pos = start + 1;
+                continue;
+            }
+            result.extend(&chars[last..start]);
+            self.expand(replacement, &chars, &caps, &mut result);
+            last = end;
+            last_match_end = Some(end);
+            pos = if start == end { end + 1 } else { end };
+        }
+        result.extend(&chars[last..]);
+        result
+    }
+
+    /// Runs the program over the text from the start and returns the slots
+    /// of the preferred match. With `full` the match must span from the start
+    /// to the end of the text.
+    fn run(&self, text: &[char], start: usize, full: bool) -> Option<Slots> {
+        let slots = 2 * (self.groups + 1);
+        let mut current = Threads::new(self.program.len(), slots);
+        let mut next = Threads::new(self.program.len(), slots);
+        let mut scratch = vec![None; slots];
+        let mut stack = vec![];
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            // A thread starting at the position has the lowest priority.
+            if matched.is_none() && (pos == start || !full) {
+                scratch.fill(None);
+                self.add_thread(&mut current, 0, text, pos, &mut scratch, &mut stack);
+            }
+            // Without the threads only the new starts may still match.
+            if current.pcs.is_empty() && (matched.is_some() || full) {
+                break;
+            }
+            for &pc in current.pcs.iter() {
+                let consumed = match &self.program[pc] {
+                    Inst::Char(c) => text.get(pos) == Some(c),
+                    Inst::Any => text.get(pos).is_some_and(|c| *c != '\n'),
+                    Inst::Class(class) => text.get(pos).is_some_and(|c| class.matches(*c)),
+                    Inst::Match if !full || pos == text.len() => {
+                        matched = Some(current.slots[pc].clone());
+                        // The remaining threads have lower priorities.
+                        break;
+                    }
+                    _ => false,
+                };
+                if consumed {
+                    scratch.clone_from(&current.slots[pc]);
+                    self.add_thread(&mut next, pc + 1, text, pos + 1, &mut scratch, &mut stack);
+                }
+            }
+            if pos == text.len() {
+                break;
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            pos += 1;
+        }
+        matched
+    }
+
+    /// Adds the thread at the instruction following the jumps, the splits,
+    /// the saves and the assertions without consuming the text. The stack
+    /// replaces the recursion, the saved slots are restored on the way back.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        text: &[char],
+        pos: usize,
+        slots: &mut Slots,
+        stack: &mut Vec<Frame>,
+    ) {
+        stack.push(Frame::Explore(pc));
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::Restore(slot, value) => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            if threads.visited[pc] {
+                continue;
+            }
+            threads.visited[pc] = true;
+            match &self.program[pc] {
+                Inst::Jump(target) => stack.push(Frame::Explore(*target)),
+                Inst::Split(first, second) => {
+                    stack.push(Frame::Explore(*second));
+                    stack.push(Frame::Explore(*first));
+                }
+                Inst::Save(slot) => {
+                    stack.push(Frame::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(pos);
+                    stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Start if pos == 0 => stack.push(Frame::Explore(pc + 1)),
+                Inst::End if pos == text.len() => stack.push(Frame::Explore(pc + 1)),
+                Inst::WordBoundary(expected) => {
+                    let before = pos > 0 && is_word(text[pos - 1]);
+                    let after = text.get(pos).is_some_and(|c| is_word(*c));
+                    if (before != after) == *expected {
+                        stack.push(Frame::Explore(pc + 1));
+                    }
+                }
+                Inst::Start | Inst::End => {}
+                Inst::Char(_) | Inst::Any | Inst::Class(_) | Inst::Match => {
+                    threads.pcs.push(pc);
+                    threads.slots[pc].clone_from(slots);
+                }
+            }
+        }
+    }
+
+    fn expand(&self, replacement: &str, chars: &[char], caps: &Captures, out: &mut String) {
+        let mut rest = replacement;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(r) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = r;
+                continue;
+            }
+            let (name, r) = if let Some(braced) = rest.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                }
+            } else {
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            };
+            if name.is_empty() {
+                out.push('$');
+                continue;
+            }
+            rest = r;
+            let index = name.parse::<usize>().ok().or_else(|| {
+                self.names
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, index)| *index)
+            });
+            if let Some(Some((start, end))) = index.and_then(|i| caps.get(i)) {
+                out.extend(&chars[*start..*end]);
+            }
+        }
+        out.push_str(rest);
+    }
+}
+
+enum Frame {
+    Explore(usize),
+    Restore(usize, Option<usize>),
+}
+
+/// The threads of the Pike VM at a text position in the priority order, at
+/// most one per instruction.
+struct Threads {
+    pcs: Vec<usize>,
+    visited: Vec<bool>,
+    slots: Vec<Slots>,
+}
+
+impl Threads {
+    fn new(len: usize, slots: usize) -> Threads {
+        Threads {
+            pcs: Vec::with_capacity(len),
+            visited: vec![false; len],
+            slots: vec![vec![None; slots]; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        self.visited.fill(false);
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::WordBoundary(expected) => program.push(Inst::WordBoundary(*expected)),
+        Node::Group(None, inner) => compile(inner, program),
+        Node::Group(Some(index), inner) => {
+            program.push(Inst::Save(2 * index));
+            compile(inner, program);
+            program.push(Inst::Save(2 * index + 1));
+        }
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alt(alternatives) => {
+            let mut jumps = vec![];
+            let (last, rest) = alternatives.split_last().unwrap();
+            for alternative in rest {
+                let split = program.len();
+                program.push(Inst::Split(split + 1, 0));
+                compile(alternative, program);
+                jumps.push(program.len());
+                program.push(Inst::Jump(0));
+                program[split] = Inst::Split(split + 1, program.len());
+            }
+            compile(last, program);
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            let split = |program: &mut Vec<Inst>, at: usize, end: usize| {
+                program[at] = match greedy {
+                    true => Inst::Split(at + 1, end),
+                    false => Inst::Split(end, at + 1),
+                };
+            };
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Inst::Jump(0));
+                    compile(node, program);
+                    program.push(Inst::Jump(start));
+                    let end = program.len();
+                    split(program, start, end);
+                }
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for at in splits {
+                        split(program, at, end);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_all_handles_long_input() {
+        let text = "x".repeat(200_000);
+        let regex = Regex::new("x.*").unwrap();
+        assert_eq!(regex.replace_all(&text, "y"), "y");
+        let regex = Regex::new("(x)").unwrap();
+        assert_eq!(regex.replace_all(&text, "${1}y").len(), 400_000);
+    }
+
+    #[test]
+    fn matches_long_input() {
+        let text = "x".repeat(200_000);
+        assert!(Regex::new("x+").unwrap().is_full_match(&text));
+        assert!(Regex::new("(x|y)*").unwrap().is_full_match(&text));
+        assert!(!Regex::new("x*y").unwrap().is_full_match(&text));
+        assert!(!Regex::new("x*y").unwrap().is_match(&text));
+    }
+
+    #[test]
+    fn replace_all_prefers_leftmost_first_matches() {
+        let replace = |pattern: &str, text: &str, replacement: &str| {
+            Regex::new(pattern).unwrap().replace_all(text, replacement)
+        };
+        assert_eq!(replace("a|ab", "ab", "[$0]"), "[a]b");
+        assert_eq!(replace("a+?", "aaa", "<$0>"), "<a><a><a>");
+        assert_eq!(replace("(\\w+)@(\\w+)", "me@host", "$2 $1"), "host me");
+        assert_eq!(replace("x*", "abc", "-"), "-a-b-c-");
+        assert_eq!(
+            replace("(?P<n>\\d{2,3})", "1 12 1234", "<${n}>"),
+            "1 <12> <123>4"
+        );
+        assert_eq!(replace("^a|c$", "abc", "_"), "_b_");
+        assert_eq!(
+            replace("\\bcat\\b", "cat concat cat", "dog"),
+            "dog concat dog"
+        );
+    }
+}
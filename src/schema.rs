@@ -0,0 +1,387 @@
+//! Validation of the documents against a JSON Schema for `--validate-schema`.
+//!
+//! Supports the commonly used subset of the draft 7 and 2020-12 keywords:
+//!
+//! * `type`, `enum` and `const`;
+//! * `properties`, `patternProperties`, `additionalProperties`, `required`,
+//!   `minProperties` and `maxProperties`;
+//! * `items`, `prefixItems`, `minItems`, `maxItems` and `uniqueItems`;
+//! * `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum` and
+//!   `multipleOf`;
+//! * `minLength`, `maxLength` and `pattern`;
+//! * `allOf`, `anyOf`, `oneOf` and `not`;
+//! * `$ref` to the `#/...` pointers within the schema, e.g. `#/$defs/port`.
+//!
+//! The other keywords, e.g. `format`, are ignored.
+
+use crate::output::scalar_to_string;
+use crate::regex::Regex;
+use crate::Result;
+use serde_yaml::Value;
+
+/// The maximum depth of the nested `$ref`s, to stop on the cyclic ones.
+const MAX_REF_DEPTH: usize = 64;
+
+/// Reads the JSON or YAML schema file.
+pub fn load_schema(path: &std::path::Path) -> Result<Value> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| error!(Io: "Failed to read the schema file `{path:?}`\nerror=`{e}`"))?;
+    serde_yaml::from_str(&content)
+        .map_err(|e| error!(Parse: "Failed to parse the schema file `{path:?}`: {e}"))
+}
+
+/// Validates the document against the schema, failing with all of the
+/// violations and their paths.
+pub fn validate(doc: &Value, schema: &Value) -> Result<()> {
+    let mut validator = Validator {
+        root: schema,
+        path: vec![],
+        errors: vec![],
+        depth: 0,
+    };
+    validator.validate(doc, schema)?;
+    if !validator.errors.is_empty() {
+        bail!(
+            "The document does not match the schema:\n{}",
+            validator.errors.join("\n")
+        );
+    }
+    Ok(())
+}
+
+struct Validator<'a> {
+    root: &'a Value,
+    path: Vec<String>,
+    errors: Vec<String>,
+    depth: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn fail(&mut self, message: String) {
+        self.errors
+            .push(format!("  [{}]: {message}", self.path.join(", ")));
+    }
+
+    /// Returns whether the value matches the schema without reporting.
+    fn matches(&mut self, value: &Value, schema: &'a Value) -> Result<bool> {
+        let errors = std::mem::take(&mut self.errors);
+        self.validate(value, schema)?;
+        let matches = self.errors.is_empty();
+        self.errors = errors;
+        Ok(matches)
+    }
+
+    fn validate(&mut self, value: &Value, schema: &'a Value) -> Result<()> {
+        let value = match value {
+            Value::Tagged(tagged) => &tagged.value,
+            value => value,
+        };
+        let schema = match schema {
+            Value::Bool(true) => return Ok(()),
+            Value::Bool(false) => {
+                self.fail("no value is allowed".to_string());
+                return Ok(());
+            }
+            Value::Mapping(schema) => schema,
+            schema => bail!("Invalid schema, expected a mapping or a boolean: {schema:?}"),
+        };
+        if let Some(reference) = schema.get("$ref") {
+            let target = self.resolve(reference)?;
+            if self.depth == MAX_REF_DEPTH {
+                bail!("The schema `$ref`s are nested too deeply, e.g. cyclic: {reference:?}");
+            }
+            self.depth += 1;
+            self.validate(value, target)?;
+            self.depth -= 1;
+        }
+        if let Some(types) = schema.get("type") {
+            let types = match types {
+                Value::Sequence(types) => types.iter().collect(),
+                t => vec![t],
+            };
+            if !types
+                .iter()
+                .any(|t| t.as_str().is_some_and(|t| has_type(value, t)))
+            {
+                let names: Vec<String> = types.iter().map(|t| scalar_to_string(t)).collect();
+                self.fail(format!(
+                    "{} is not of the type {}",
+                    repr(value),
+                    names.join(" or ")
+                ));
+            }
+        }
+        if let Some(Value::Sequence(options)) = schema.get("enum") {
+            if !options.iter().any(|o| equal(o, value)) {
+                self.fail(format!(
+                    "{} is not one of {}",
+                    repr(value),
+                    repr_seq(options)
+                ));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !equal(expected, value) {
+                self.fail(format!("{} is not {}", repr(value), repr(expected)));
+            }
+        }
+        match value {
+            Value::Mapping(map) => self.validate_mapping(map, schema)?,
+            Value::Sequence(seq) => self.validate_sequence(seq, schema)?,
+            Value::Number(n) => self.validate_number(n.as_f64().unwrap_or(f64::NAN), schema),
+            Value::String(s) => self.validate_string(s, schema)?,
+            _ => {}
+        }
+        if let Some(Value::Sequence(all)) = schema.get("allOf") {
+            for s in all {
+                self.validate(value, s)?;
+            }
+        }
+        if let Some(Value::Sequence(any)) = schema.get("anyOf") {
+            let mut matched = false;
+            for s in any {
+                matched = matched || self.matches(value, s)?;
+            }
+            if !matched {
+                self.fail("does not match any of the `anyOf` schemas".to_string());
+            }
+        }
+        if let Some(Value::Sequence(one)) = schema.get("oneOf") {
+            let mut matched = 0;
+            for s in one {
+                matched += usize::from(self.matches(value, s)?);
+            }
+            if matched != 1 {
+                self.fail(format!(
+                    "matches {matched} of the `oneOf` schemas instead of one"
+                ));
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.matches(value, not)? {
+                self.fail("matches the `not` schema".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_mapping(
+        &mut self,
+        map: &serde_yaml::Mapping,
+        schema: &'a serde_yaml::Mapping,
+    ) -> Result<()> {
+        if let Some(Value::Sequence(required)) = schema.get("required") {
+            for key in required {
+                if !map.contains_key(key) {
+                    self.fail(format!(
+                        "the required key `{}` is missing",
+                        scalar_to_string(key)
+                    ));
+                }
+            }
+        }
+        self.check_count(map.len(), schema, "minProperties", "maxProperties", "keys");
+        let properties = schema.get("properties").and_then(Value::as_mapping);
+        let mut patterns = vec![];
+        if let Some(Value::Mapping(pattern_properties)) = schema.get("patternProperties") {
+            for (pattern, s) in pattern_properties {
+                let pattern = scalar_to_string(pattern);
+                let regex = Regex::new(&pattern)
+                    .map_err(|e| error!("Invalid schema pattern `{pattern}`: {e}"))?;
+                patterns.push((regex, s));
+            }
+        }
+        for (key, value) in map {
+            let key_string = scalar_to_string(key);
+            self.path.push(key_string.clone());
+            let mut known = false;
+            if let Some(s) = properties.and_then(|p| p.get(key)) {
+                known = true;
+                self.validate(value, s)?;
+            }
+            for (regex, s) in &patterns {
+                if regex.is_match(&key_string) {
+                    known = true;
+                    self.validate(value, s)?;
+                }
+            }
+            if !known {
+                match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        self.path.pop();
+                        self.fail(format!("the key `{key_string}` is not allowed"));
+                        continue;
+                    }
+                    Some(s) => self.validate(value, s)?,
+                    None => {}
+                }
+            }
+            self.path.pop();
+        }
+        Ok(())
+    }
+
+    fn validate_sequence(&mut self, seq: &[Value], schema: &'a serde_yaml::Mapping) -> Result<()> {
+        self.check_count(seq.len(), schema, "minItems", "maxItems", "items");
+        // The draft 7 `items` sequence is the 2020-12 `prefixItems`.
+        let (prefix, rest) = match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(Value::Sequence(prefix)), items) => (prefix.as_slice(), items),
+            (None, Some(Value::Sequence(prefix))) => {
+                (prefix.as_slice(), schema.get("additionalItems"))
+            }
+            (_, items) => (&[][..], items),
+        };
+        for (i, value) in seq.iter().enumerate() {
+            let s = match prefix.get(i) {
+                Some(s) => s,
+                None => match rest {
+                    Some(s) => s,
+                    None => continue,
+                },
+            };
+            self.path.push(i.to_string());
+            self.validate(value, s)?;
+            self.path.pop();
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            for (i, value) in seq.iter().enumerate() {
+                if seq[..i].iter().any(|v| equal(v, value)) {
+                    self.fail(format!("the item {} is not unique", repr(value)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_number(&mut self, n: f64, schema: &serde_yaml::Mapping) {
+        let limit = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+        if let Some(min) = limit("minimum").filter(|min| n < *min) {
+            self.fail(format!("{n} is less than the minimum {min}"));
+        }
+        if let Some(max) = limit("maximum").filter(|max| n > *max) {
+            self.fail(format!("{n} is greater than the maximum {max}"));
+        }
+        if let Some(min) = limit("exclusiveMinimum").filter(|min| n <= *min) {
+            self.fail(format!(
+                "{n} is not greater than the exclusive minimum {min}"
+            ));
+        }
+        if let Some(max) = limit("exclusiveMaximum").filter(|max| n >= *max) {
+            self.fail(format!("{n} is not less than the exclusive maximum {max}"));
+        }
+        if let Some(factor) = limit("multipleOf").filter(|f| *f > 0.0) {
+            let quotient = n / factor;
+            if (quotient - quotient.round()).abs() > 1e-9 {
+                self.fail(format!("{n} is not a multiple of {factor}"));
+            }
+        }
+    }
+
+    fn validate_string(&mut self, s: &str, schema: &serde_yaml::Mapping) -> Result<()> {
+        self.check_count(
+            s.chars().count(),
+            schema,
+            "minLength",
+            "maxLength",
+            "characters",
+        );
+        if let Some(pattern) = schema.get("pattern") {
+            let pattern = scalar_to_string(pattern);
+            let regex = Regex::new(&pattern)
+                .map_err(|e| error!("Invalid schema pattern `{pattern}`: {e}"))?;
+            if !regex.is_match(s) {
+                self.fail(format!(
+                    "{} does not match the pattern `{pattern}`",
+                    repr_str(s)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_count(
+        &mut self,
+        count: usize,
+        schema: &serde_yaml::Mapping,
+        min: &str,
+        max: &str,
+        what: &str,
+    ) {
+        if let Some(min) = schema.get(min).and_then(Value::as_u64) {
+            if (count as u64) < min {
+                self.fail(format!("has {count} {what}, fewer than {min}"));
+            }
+        }
+        if let Some(max) = schema.get(max).and_then(Value::as_u64) {
+            if count as u64 > max {
+                self.fail(format!("has {count} {what}, more than {max}"));
+            }
+        }
+    }
+
+    fn resolve(&self, reference: &Value) -> Result<&'a Value> {
+        let reference = reference
+            .as_str()
+            .ok_or_else(|| error!("The schema `$ref` is not a string: {reference:?}"))?;
+        let pointer = reference.strip_prefix('#').ok_or_else(|| {
+            error!("Only the `#/...` schema `$ref`s within the schema are supported: `{reference}`")
+        })?;
+        let mut target = self.root;
+        for token in pointer.split('/').skip(1) {
+            let token = token.replace("~1", "/").replace("~0", "~");
+            let next = match target {
+                Value::Sequence(seq) => token.parse::<usize>().ok().and_then(|i| seq.get(i)),
+                v => v.get(token.as_str()),
+            };
+            target = next.ok_or_else(|| error!("No schema at the `$ref` `{reference}`"))?;
+        }
+        Ok(target)
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("null", Value::Null) | ("boolean", Value::Bool(_)) | ("number", Value::Number(_)) => true,
+        ("integer", Value::Number(n)) => {
+            n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        ("string", Value::String(_))
+        | ("array", Value::Sequence(_))
+        | ("object", Value::Mapping(_)) => true,
+        _ => false,
+    }
+}
+
+/// Compares the values as JSON values, e.g. `1` equals `1.0`.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Tagged(a), b) => equal(&a.value, b),
+        (a, Value::Tagged(b)) => equal(a, &b.value),
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (Value::Sequence(a), Value::Sequence(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Mapping(a), Value::Mapping(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(k, v)| b.get(k).is_some_and(|other| equal(v, other)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn repr(value: &Value) -> String {
+    match value {
+        Value::String(s) => repr_str(s),
+        v => format!("`{}`", crate::output::to_flow_yaml(v).trim_end()),
+    }
+}
+
+fn repr_str(s: &str) -> String {
+    format!("`{}`", crate::output::double_quote(s))
+}
+
+fn repr_seq(values: &[Value]) -> String {
+    let values: Vec<String> = values.iter().map(repr).collect();
+    values.join(", ")
+}
@@ -0,0 +1,436 @@
+//! A small Handlebars-style template renderer.
+//!
+//! The final YAML document is used as the rendering context. Supported
+//! syntax:
+//!
+//! * `{{path.to.value}}` - insert a value escaping the HTML special
+//!   characters, `{{{path.to.value}}}` and `{{&path.to.value}}` - insert it
+//!   as is;
+//! * `{{this}}`, `{{@index}}`, `{{@key}}`, `{{@first}}`, `{{@last}}`,
+//!   `{{../path}}` and `{{@root.path}}`;
+//! * `{{#each path}}...{{else}}...{{/each}}` over sequences and mappings;
+//! * `{{#if path}}`, `{{#unless path}}` and `{{#with path}}` blocks;
+//! * `{{! comment }}` and `{{!-- comment --}}`;
+//! * `~` whitespace control, e.g. `{{~path~}}`.
+//!
+//! Block tags standing alone on a line remove the whole line from the output.
+
+use crate::Result;
+use serde_yaml::Value;
+use std::path::Path;
+
+const HELPERS: [&str; 4] = ["if", "unless", "with", "each"];
+
+enum Token {
+    Text(String),
+    /// The expression and whether its value is HTML-escaped.
+    Expr(String, bool),
+    Open(String, String),
+    Else,
+    Close(String),
+    Comment,
+}
+
+impl Token {
+    fn is_standalone_candidate(&self) -> bool {
+        !matches!(self, Token::Text(_) | Token::Expr(..))
+    }
+}
+
+enum Node {
+    Text(String),
+    Expr(String, bool),
+    Block {
+        helper: String,
+        arg: String,
+        body: Vec<Node>,
+        inverse: Vec<Node>,
+    },
+}
+
+struct Scope<'s> {
+    value: &'s Value,
+    root: &'s Value,
+    parent: Option<&'s Scope<'s>>,
+    index: Option<usize>,
+    key: Option<Value>,
+    len: usize,
+}
+
+pub fn render_file(path: &Path, context: &Value) -> Result<String> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|e| error!("Failed to read the template file `{path:?}`\nerror=`{e}`"))?;
+    render(&template, context)
+}
+
+pub fn render(template: &str, context: &Value) -> Result<String> {
+    let mut tokens = tokenize(template)?;
+    strip_standalone(&mut tokens);
+    let mut tokens = tokens.into_iter();
+    let (nodes, _) = parse_until(&mut tokens, None)?;
+    let scope = Scope {
+        value: context,
+        root: context,
+        parent: None,
+        index: None,
+        key: None,
+        len: 0,
+    };
+    let mut out = String::new();
+    render_nodes(&nodes, &scope, &mut out)?;
+    Ok(out)
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = template;
+    let mut trim_next = false;
+    while let Some(start) = rest.find("{{") {
+        let mut text = rest[..start].to_string();
+        if trim_next {
+            text = text.trim_start().to_string();
+        }
+        let after = &rest[start..];
+        let (raw, close) = if after.starts_with("{{{") {
+            ("{{{", "}}}")
+        } else if after.starts_with("{{!--") || after.starts_with("{{~!--") {
+            ("{{", "--}}")
+        } else {
+            ("{{", "}}")
+        };
+        let end = after[raw.len()..]
+            .find(close)
+            .ok_or_else(|| error!("Unclosed template tag at:\n`{}`", first_line(after)))?
+            + raw.len();
+        let mut content = &after[raw.len()..end];
+        if let Some(c) = content.strip_prefix('~') {
+            text = text.trim_end().to_string();
+            content = c;
+        }
+        trim_next = false;
+        if let Some(c) = content.strip_suffix('~') {
+            trim_next = true;
+            content = c;
+        }
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        tokens.push(tag_token(content.trim(), raw == "{{"));
+        rest = &after[end + close.len()..];
+    }
+    let mut text = rest.to_string();
+    if trim_next {
+        text = text.trim_start().to_string();
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+fn tag_token(content: &str, escape: bool) -> Token {
+    if content.starts_with('!') {
+        Token::Comment
+    } else if let Some(block) = content.strip_prefix('#') {
+        let (helper, arg) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+        Token::Open(helper.to_string(), arg.trim().to_string())
+    } else if let Some(helper) = content.strip_prefix('/') {
+        Token::Close(helper.trim().to_string())
+    } else if content == "else" || content == "^" {
+        Token::Else
+    } else if let Some(expr) = content.strip_prefix('&') {
+        Token::Expr(expr.trim().to_string(), false)
+    } else {
+        Token::Expr(content.to_string(), escape)
+    }
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or_default()
+}
+
+/// Removes the whitespace and the line break around block tags and comments
+/// that are the only content of their line.
+fn strip_standalone(tokens: &mut [Token]) {
+    let standalone: Vec<bool> = (0..tokens.len())
+        .map(|i| {
+            let before_ok = match i.checked_sub(1).map(|j| &tokens[j]) {
+                None => true,
+                Some(Token::Text(t)) => {
+                    let tail = &t[t.rfind('\n').map(|p| p + 1).unwrap_or(0)..];
+                    tail.trim().is_empty() && (t.contains('\n') || i == 1)
+                }
+                Some(_) => false,
+            };
+            let after_ok = match tokens.get(i + 1) {
+                None => true,
+                Some(Token::Text(t)) => t[..t.find('\n').unwrap_or(t.len())].trim().is_empty(),
+                Some(_) => false,
+            };
+            tokens[i].is_standalone_candidate() && before_ok && after_ok
+        })
+        .collect();
+    for (i, _) in standalone.iter().enumerate().filter(|(_, s)| **s) {
+        if let Some(Token::Text(t)) = i.checked_sub(1).map(|j| &mut tokens[j]) {
+            let cut = t.rfind('\n').map(|p| p + 1).unwrap_or(0);
+            t.truncate(cut);
+        }
+        if let Some(Token::Text(t)) = tokens.get_mut(i + 1) {
+            let cut = t.find('\n').map(|p| p + 1).unwrap_or(t.len());
+            t.drain(..cut);
+        }
+    }
+}
+
+fn parse_until(
+    tokens: &mut impl Iterator<Item = Token>,
+    open: Option<&str>,
+) -> Result<(Vec<Node>, bool)> {
+    let mut nodes = vec![];
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(t) => nodes.push(Node::Text(t)),
+            Token::Expr(e, escape) => nodes.push(Node::Expr(e, escape)),
+            Token::Comment => {}
+            Token::Open(helper, arg) => {
+                if !HELPERS.contains(&helper.as_str()) {
+                    bail!("Unsupported template block helper `{helper}`");
+                }
+                let (body, has_inverse) = parse_until(tokens, Some(&helper))?;
+                let inverse = if has_inverse {
+                    let (inverse, nested_else) = parse_until(tokens, Some(&helper))?;
+                    if nested_else {
+                        bail!("Multiple `{{{{else}}}}` in the `{helper}` block");
+                    }
+                    inverse
+                } else {
+                    vec![]
+                };
+                nodes.push(Node::Block {
+                    helper,
+                    arg,
+                    body,
+                    inverse,
+                });
+            }
+            Token::Else => {
+                if open.is_none() {
+                    bail!("Unexpected `{{{{else}}}}` outside of a block");
+                }
+                return Ok((nodes, true));
+            }
+            Token::Close(helper) => match open {
+                Some(open) if open == helper => return Ok((nodes, false)),
+                Some(open) => {
+                    bail!("Mismatched template block: opened `{open}`, closed `{helper}`")
+                }
+                None => bail!("Unexpected template block close `{helper}`"),
+            },
+        }
+    }
+    if let Some(open) = open {
+        bail!("Unclosed template block `{open}`");
+    }
+    Ok((nodes, false))
+}
+
+fn render_nodes(nodes: &[Node], scope: &Scope, out: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Expr(e, escape) => {
+                if let Some(v) = resolve(scope, e) {
+                    match escape {
+                        true => out.push_str(&escape_html(&display(&v)?)),
+                        false => out.push_str(&display(&v)?),
+                    }
+                }
+            }
+            Node::Block {
+                helper,
+                arg,
+                body,
+                inverse,
+            } => render_block(helper, arg, body, inverse, scope, out)?,
+        }
+    }
+    Ok(())
+}
+
+fn render_block(
+    helper: &str,
+    arg: &str,
+    body: &[Node],
+    inverse: &[Node],
+    scope: &Scope,
+    out: &mut String,
+) -> Result<()> {
+    let value = resolve(scope, arg).unwrap_or(Value::Null);
+    match helper {
+        "if" | "unless" => {
+            if is_truthy(&value) == (helper == "if") {
+                render_nodes(body, scope, out)?;
+            } else {
+                render_nodes(inverse, scope, out)?;
+            }
+        }
+        "with" => {
+            if is_truthy(&value) {
+                let inner = child_scope(scope, &value, None, None, 0);
+                render_nodes(body, &inner, out)?;
+            } else {
+                render_nodes(inverse, scope, out)?;
+            }
+        }
+        "each" => {
+            let mut rendered = false;
+            match untag(&value) {
+                Value::Sequence(seq) => {
+                    for (i, item) in seq.iter().enumerate() {
+                        let inner = child_scope(scope, item, Some(i), None, seq.len());
+                        render_nodes(body, &inner, out)?;
+                        rendered = true;
+                    }
+                }
+                Value::Mapping(map) => {
+                    for (i, (key, item)) in map.iter().enumerate() {
+                        let inner = child_scope(scope, item, Some(i), Some(key.clone()), map.len());
+                        render_nodes(body, &inner, out)?;
+                        rendered = true;
+                    }
+                }
+                _ => {}
+            }
+            if !rendered {
+                render_nodes(inverse, scope, out)?;
+            }
+        }
+        _ => unreachable!("block helpers are validated by parse_until"),
+    }
+    Ok(())
+}
+
+fn child_scope<'s>(
+    parent: &'s Scope<'s>,
+    value: &'s Value,
+    index: Option<usize>,
+    key: Option<Value>,
+    len: usize,
+) -> Scope<'s> {
+    Scope {
+        value,
+        root: parent.root,
+        parent: Some(parent),
+        index,
+        key,
+        len,
+    }
+}
+
+fn resolve(scope: &Scope, expr: &str) -> Option<Value> {
+    let mut scope = scope;
+    let mut expr = expr.trim();
+    while let Some(rest) = expr.strip_prefix("../") {
+        scope = scope.parent.unwrap_or(scope);
+        expr = rest;
+    }
+    match expr {
+        "@index" => return scope.index.map(|i| Value::from(i as u64)),
+        "@key" => return scope.key.clone(),
+        "@first" => return scope.index.map(|i| Value::Bool(i == 0)),
+        "@last" => return scope.index.map(|i| Value::Bool(i + 1 == scope.len)),
+        _ => {}
+    }
+    let (mut current, path) = if let Some(path) = strip_keyword(expr, "@root") {
+        (scope.root, path)
+    } else if let Some(path) = strip_keyword(expr, "this") {
+        (scope.value, path)
+    } else if expr == "." {
+        (scope.value, "")
+    } else {
+        (scope.value, expr)
+    };
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        current = match untag(current) {
+            Value::Sequence(seq) => seq.get(segment.parse::<usize>().ok()?)?,
+            Value::Mapping(map) => map.get(segment)?,
+            _ => return None,
+        };
+    }
+    Some(current.clone())
+}
+
+/// Returns the path after the keyword if the expression is the keyword or
+/// starts with it followed by `.` or `/`, e.g. `this.name` but not `thistle`.
+fn strip_keyword<'e>(expr: &'e str, keyword: &str) -> Option<&'e str> {
+    let rest = expr.strip_prefix(keyword)?;
+    match rest.is_empty() {
+        true => Some(rest),
+        false => rest.strip_prefix(['.', '/']),
+    }
+}
+
+fn untag(value: &Value) -> &Value {
+    match value {
+        Value::Tagged(tagged) => untag(&tagged.value),
+        _ => value,
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match untag(value) {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Sequence(seq) => !seq.is_empty(),
+        Value::Mapping(_) => true,
+        Value::Tagged(_) => unreachable!(),
+    }
+}
+
+/// Escapes the characters Handlebars escapes in the `{{...}}` expressions.
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            '`' => escaped.push_str("&#x60;"),
+            '=' => escaped.push_str("&#x3D;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn display(value: &Value) -> Result<String> {
+    Ok(match untag(value) {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        v => crate::output::to_yaml(v)?.trim_end().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_starting_with_keywords_are_paths() {
+        let context: Value =
+            serde_yaml::from_str("{thistle: purple, this_host: h1, '@rooted': r, a: {b: c}}")
+                .unwrap();
+        let rendered = render(
+            "{{thistle}} {{this_host}} {{@rooted}} {{this.a.b}} {{@root.a.b}} {{#with a}}{{this/b}}{{/with}}",
+            &context,
+        )
+        .unwrap();
+        assert_eq!(rendered, "purple h1 r c c c");
+    }
+}
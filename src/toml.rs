@@ -0,0 +1,441 @@
+//! A parser for TOML documents, the input of `--input-format toml`.
+//!
+//! The tables become mappings and the arrays become sequences. The offset and
+//! local dates and times have no YAML counterparts and are kept as strings.
+
+use serde_yaml::Mapping;
+use serde_yaml::Value;
+use std::collections::HashSet;
+
+pub fn parse(content: &str) -> std::result::Result<Value, String> {
+    let mut parser = Parser {
+        chars: content.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser
+        .parse_document()
+        .map_err(|e| format!("line {}: {e}", parser.line))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let matches = s
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c));
+        if matches {
+            s.chars().for_each(|_| {
+                self.next();
+            });
+        }
+        matches
+    }
+
+    /// Consumes the characters matching the predicate, which are never line
+    /// breaks.
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek().filter(|c| predicate(*c)) {
+            s.push(c);
+            self.pos += 1;
+        }
+        s
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.next();
+        }
+    }
+
+    /// Skips the whitespace, the line breaks and the comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('\n' | '\r') => {
+                    self.next();
+                }
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        self.eat("\r");
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(format!("unexpected `{c}` at the end of the line")),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Value, String> {
+        let mut root = Mapping::new();
+        let mut current = vec![];
+        // The tables with the `[table]` headers, which cannot be redefined.
+        let mut defined: HashSet<Vec<String>> = HashSet::new();
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => return Ok(Value::Mapping(root)),
+                Some('[') => {
+                    self.next();
+                    let is_array = self.eat("[");
+                    self.skip_spaces();
+                    let keys = self.parse_key()?;
+                    self.skip_spaces();
+                    let close = if is_array { "]]" } else { "]" };
+                    if !self.eat(close) {
+                        return Err(format!("expected `{close}` after the table name"));
+                    }
+                    if is_array {
+                        let (last, parents) = keys.split_last().unwrap();
+                        let tables = descend(&mut root, parents)?
+                            .entry(Value::String(last.clone()))
+                            .or_insert_with(|| Value::Sequence(vec![]));
+                        let Value::Sequence(tables) = tables else {
+                            return Err(format!("`{}` is not an array of tables", keys.join(".")));
+                        };
+                        tables.push(Value::Mapping(Mapping::new()));
+                        // The sub-tables of the new table may be defined anew.
+                        defined.retain(|table| !table.starts_with(&keys));
+                    } else {
+                        if !defined.insert(keys.clone()) {
+                            return Err(format!("duplicate table `[{}]`", keys.join(".")));
+                        }
+                        descend(&mut root, &keys)?;
+                    }
+                    current = keys;
+                    self.end_of_line()?;
+                }
+                Some(_) => {
+                    let keys = self.parse_key()?;
+                    self.skip_spaces();
+                    if !self.eat("=") {
+                        return Err(format!("expected `=` after the key `{}`", keys.join(".")));
+                    }
+                    self.skip_spaces();
+                    let value = self.parse_value()?;
+                    insert(descend(&mut root, &current)?, &keys, value)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    /// Parses a bare, quoted or dotted key into its parts.
+    fn parse_key(&mut self) -> Result<Vec<String>, String> {
+        let mut keys = vec![];
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some('"') => {
+                    self.next();
+                    self.parse_basic_string()?
+                }
+                Some('\'') => {
+                    self.next();
+                    self.parse_literal_string()?
+                }
+                _ => {
+                    let key =
+                        self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+                    if key.is_empty() {
+                        return Err(match self.peek() {
+                            Some(c) if !c.is_whitespace() => {
+                                format!("expected a key, found `{c}`")
+                            }
+                            _ => "expected a key".to_string(),
+                        });
+                    }
+                    key
+                }
+            };
+            keys.push(key);
+            self.skip_spaces();
+            if !self.eat(".") {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        let value = match self.peek() {
+            None => return Err("expected a value".to_string()),
+            Some('"') if self.eat("\"\"\"") => Value::String(self.parse_multiline_string(true)?),
+            Some('\'') if self.eat("'''") => Value::String(self.parse_multiline_string(false)?),
+            Some('"') => {
+                self.next();
+                Value::String(self.parse_basic_string()?)
+            }
+            Some('\'') => {
+                self.next();
+                Value::String(self.parse_literal_string()?)
+            }
+            Some('[') => {
+                self.next();
+                self.parse_array()?
+            }
+            Some('{') => {
+                self.next();
+                self.parse_inline_table()?
+            }
+            Some(_) => self.parse_scalar()?,
+        };
+        Ok(value)
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.parse_escape()?),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Parses the rest of a `"""` string, or of a `'''` one without the
+    /// escapes. A line break right after the opening quotes is dropped.
+    fn parse_multiline_string(&mut self, basic: bool) -> Result<String, String> {
+        let quote = if basic { '"' } else { '\'' };
+        let delimiter = quote.to_string().repeat(3);
+        if !self.eat("\r\n") {
+            self.eat("\n");
+        }
+        let mut s = String::new();
+        loop {
+            if self.eat(&delimiter) {
+                // Up to two quotes may precede the closing delimiter.
+                for _ in 0..2 {
+                    if self.eat(&quote.to_string()) {
+                        s.push(quote);
+                    }
+                }
+                return Ok(s);
+            }
+            match self.next() {
+                None => return Err("unterminated string".to_string()),
+                Some('\\') if basic => {
+                    // A backslash at the end of a line trims the following
+                    // whitespace.
+                    let mut ahead = 0;
+                    while matches!(self.peek_at(ahead), Some(' ' | '\t' | '\r')) {
+                        ahead += 1;
+                    }
+                    if self.peek_at(ahead) == Some('\n') {
+                        while self.peek().is_some_and(char::is_whitespace) {
+                            self.next();
+                        }
+                    } else {
+                        s.push(self.parse_escape()?);
+                    }
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let c = match self.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ ('u' | 'U')) => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.next()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid unicode escape `\\{u}{hex}`"))?
+            }
+            Some(c) => return Err(format!("invalid escape `\\{c}`")),
+            None => return Err("unterminated string".to_string()),
+        };
+        Ok(c)
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        let mut items = vec![];
+        loop {
+            self.skip_blank();
+            if self.eat("]") {
+                return Ok(Value::Sequence(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank();
+            if self.eat("]") {
+                return Ok(Value::Sequence(items));
+            }
+            if !self.eat(",") {
+                return Err("expected `,` or `]` in the array".to_string());
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, String> {
+        let mut table = Mapping::new();
+        self.skip_spaces();
+        if self.eat("}") {
+            return Ok(Value::Mapping(table));
+        }
+        loop {
+            let keys = self.parse_key()?;
+            self.skip_spaces();
+            if !self.eat("=") {
+                return Err(format!("expected `=` after the key `{}`", keys.join(".")));
+            }
+            self.skip_spaces();
+            let value = self.parse_value()?;
+            insert(&mut table, &keys, value)?;
+            self.skip_spaces();
+            if self.eat("}") {
+                return Ok(Value::Mapping(table));
+            }
+            if !self.eat(",") {
+                return Err("expected `,` or `}` in the inline table".to_string());
+            }
+            self.skip_spaces();
+        }
+    }
+
+    /// Parses a boolean, a number, or a date or time kept as a string.
+    fn parse_scalar(&mut self) -> Result<Value, String> {
+        let token = |parser: &mut Parser| {
+            parser.take_while(|c| c.is_ascii_alphanumeric() || "+-._:".contains(c))
+        };
+        let mut s = token(self);
+        let is_digits = |s: &str, range: std::ops::Range<usize>| {
+            s.get(range)
+                .is_some_and(|s| s.bytes().all(|b| b.is_ascii_digit()))
+        };
+        let is_date = is_digits(&s, 0..4) && s[4..].starts_with('-');
+        let is_time = is_digits(&s, 0..2) && s[2..].starts_with(':');
+        if is_date && s.len() == 10 && self.peek() == Some(' ') {
+            // The date and the time may be separated with a space.
+            if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+                self.next();
+                s = format!("{s} {}", token(self));
+            }
+        }
+        if is_date || is_time {
+            return Ok(Value::String(s));
+        }
+        let invalid = || format!("invalid value `{s}`");
+        if s.is_empty() {
+            return Err(match self.peek() {
+                Some(c) if !c.is_whitespace() => format!("expected a value, found `{c}`"),
+                _ => "expected a value".to_string(),
+            });
+        }
+        match s.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            "inf" | "+inf" => return Ok(Value::from(f64::INFINITY)),
+            "-inf" => return Ok(Value::from(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::from(f64::NAN)),
+            _ => {}
+        }
+        let digits = s.replace('_', "");
+        for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+            if let Some(digits) = digits.strip_prefix(prefix) {
+                return i64::from_str_radix(digits, radix)
+                    .map(Value::from)
+                    .map_err(|_| invalid());
+            }
+        }
+        match digits.contains(['.', 'e', 'E']) {
+            true => digits
+                .parse::<f64>()
+                .map(Value::from)
+                .map_err(|_| invalid()),
+            false => digits
+                .parse::<i64>()
+                .map(Value::from)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+/// Returns the table at the keys, creating the missing ones. The keys of the
+/// arrays of tables refer to their last tables.
+fn descend<'a>(table: &'a mut Mapping, keys: &[String]) -> Result<&'a mut Mapping, String> {
+    let mut table = table;
+    for key in keys {
+        let entry = table
+            .entry(Value::String(key.clone()))
+            .or_insert_with(|| Value::Mapping(Mapping::new()));
+        table = match entry {
+            Value::Mapping(table) => table,
+            Value::Sequence(tables) => match tables.last_mut() {
+                Some(Value::Mapping(table)) => table,
+                _ => return Err(format!("`{key}` is not a table")),
+            },
+            _ => return Err(format!("`{key}` is not a table")),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Mapping, keys: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = keys.split_last().unwrap();
+    let table = descend(table, parents)?;
+    let key = Value::String(last.clone());
+    if table.contains_key(&key) {
+        return Err(format!("duplicate key `{}`", keys.join(".")));
+    }
+    table.insert(key, value);
+    Ok(())
+}